@@ -0,0 +1,314 @@
+use std::fs::{read, read_to_string, write};
+use std::ops::Range;
+use std::path::Path;
+use std::sync::RwLock;
+
+use crate::config::TokenizerConfig;
+use crate::error::{Result, TokenizerError};
+use crate::fingerprint::{fingerprint, to_hex, Digest256};
+use crate::ids::IdMap;
+use crate::pretrained::Pretrained;
+use crate::schema::{check_ids, config_from_value, tokenizer_from_str};
+use crate::tokenizer::{BPETokenizer, FwdMap, Token, VocabMap};
+
+/// first bytes of every binary tokenizer file
+pub const MAGIC: &[u8; 4] = b"TKTN";
+
+/// version of the binary layout written by `to_bytes`
+///
+/// - 1: merges in id order
+/// - 2: merges sorted by pair, plus the lookup tables `MappedBPETokenizer` searches
+/// - 3: fingerprint of the tokenizer, checked on load
+pub const BINARY_VERSION: u32 = 3;
+
+/// files with this extension are saved and loaded in the binary format
+pub const BINARY_EXTENSION: &str = "tkn";
+
+// layout, all integers u32 little-endian:
+//
+//   magic, version
+//   fingerprint: 32 bytes of sha-256, see `BPETokenizer::fingerprint`
+//   config as versioned json without its special tokens: len, bytes
+//   special tokens: count, then id, len, utf-8 bytes for each
+//   merges sorted by (left, right): count, then left, right, id for each
+//   merge order: position of each merge in the table above, by increasing id
+//   external ids: count (0 if the vocabulary isn't imported), then one per internal id
+//   external order: internal ids sorted by external id, only if there are external ids
+//
+// v1 has neither of the order tables and its merges are sorted by id, v1 and
+// v2 have no fingerprint.
+
+fn corrupt<T>(msg: impl Into<String>) -> Result<T> {
+    Err(TokenizerError::Corrupt(msg.into()))
+}
+
+/// `i`th little-endian u32 of `bytes`
+pub(crate) fn u32_at(bytes: &[u8], i: usize) -> u32 {
+    u32::from_le_bytes(bytes[4 * i..4 * i + 4].try_into().unwrap())
+}
+
+/// where the tables of a binary file are, as byte ranges into it
+pub(crate) struct Tables {
+    pub merges: Range<usize>,
+    pub merge_order: Option<Range<usize>>,
+    pub ids: Range<usize>,
+    pub id_order: Option<Range<usize>>,
+    /// computed from the tables, matching the stored one if there is one
+    pub fingerprint: Digest256,
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<Range<usize>> {
+        if self.bytes.len() - self.pos < len {
+            return corrupt("unexpected end of file");
+        }
+        self.pos += len;
+        Ok(self.pos - len..self.pos)
+    }
+
+    fn slice(&mut self, len: usize) -> Result<&'a [u8]> {
+        let range = self.take(len)?;
+        Ok(&self.bytes[range])
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32_at(self.slice(4)?, 0))
+    }
+
+    // a count of items of `size` bytes each, checked against what's left so a
+    // corrupt count can't trigger a huge allocation
+    fn count(&mut self, size: usize) -> Result<usize> {
+        let count = self.u32()? as usize;
+        if count.saturating_mul(size) > self.bytes.len() - self.pos {
+            return corrupt("unexpected end of file");
+        }
+        Ok(count)
+    }
+}
+
+/// reads the config and locates the tables of a binary file, checking they
+/// are consistent so lookups into them can't loop or go out of bounds
+pub(crate) fn parse(bytes: &[u8]) -> Result<(TokenizerConfig, Tables)> {
+    let mut reader = Reader { bytes, pos: 0 };
+    if reader.slice(MAGIC.len())? != MAGIC {
+        return corrupt("not a binary tokenizer file");
+    }
+    let version = reader.u32()?;
+    if version == 0 {
+        return corrupt("binary format version 0 was never written");
+    }
+    if version > BINARY_VERSION {
+        return Err(TokenizerError::UnsupportedVersion {
+            found: version,
+            supported: BINARY_VERSION,
+        });
+    }
+    let stored = match version >= 3 {
+        true => Some(reader.slice(32)?),
+        false => None,
+    };
+
+    let len = reader.count(1)?;
+    let mut config = config_from_value(serde_json::from_slice(reader.slice(len)?)?)?;
+
+    let mut specials = VocabMap::default();
+    for _ in 0..reader.count(8)? {
+        let id = reader.u32()?;
+        let len = reader.count(1)?;
+        let Ok(token) = String::from_utf8(reader.slice(len)?.to_vec()) else {
+            return corrupt("special token is not valid utf-8");
+        };
+        if specials.insert(token, id).is_some() {
+            return corrupt("duplicate special token");
+        }
+    }
+    config.special_tokens_map = (!specials.is_empty()).then_some(specials);
+
+    let num_merges = reader.count(12)?;
+    let merges = reader.take(12 * num_merges)?;
+    let table = &bytes[merges.clone()];
+    let merge = |i: usize| (u32_at(table, 3 * i), u32_at(table, 3 * i + 1), u32_at(table, 3 * i + 2));
+    // binary search needs strictly increasing pairs
+    for i in 1..num_merges {
+        if version >= 2 && (merge(i - 1).0, merge(i - 1).1) >= (merge(i).0, merge(i).1) {
+            return corrupt("merges are not sorted");
+        }
+    }
+
+    let mut merge_order = None;
+    if version >= 2 {
+        let range = reader.take(4 * num_merges)?;
+        let order = &bytes[range.clone()];
+        // strictly increasing ids also rule out repeated positions
+        let mut last = None;
+        for i in 0..num_merges {
+            let pos = u32_at(order, i) as usize;
+            if pos >= num_merges || last >= Some(merge(pos).2) {
+                return corrupt("merge order is not sorted by id");
+            }
+            last = Some(merge(pos).2);
+        }
+        merge_order = Some(range);
+    }
+
+    let num_ids = reader.count(4)?;
+    let ids = reader.take(4 * num_ids)?;
+    check_ids(
+        (0..num_merges).map(merge),
+        config.special_tokens_map.iter().flatten().map(|(_, &id)| id),
+        num_ids,
+    )?;
+
+    let mut id_order = None;
+    if version >= 2 && num_ids != 0 {
+        let range = reader.take(4 * num_ids)?;
+        let (order, external) = (&bytes[range.clone()], &bytes[ids.clone()]);
+        let mut last = None;
+        for i in 0..num_ids {
+            let internal = u32_at(order, i) as usize;
+            if internal >= num_ids || last >= Some((u32_at(external, internal), internal)) {
+                return corrupt("external id order is not sorted");
+            }
+            last = Some((u32_at(external, internal), internal));
+        }
+        id_order = Some(range);
+    }
+
+    if reader.pos != bytes.len() {
+        return corrupt("trailing bytes after the tokenizer");
+    }
+
+    // merges in id order, which v1 stored them in
+    let by_id = |i: usize| match &merge_order {
+        Some(order) => merge(u32_at(&bytes[order.clone()], i) as usize),
+        None => merge(i),
+    };
+    let external = (0..num_ids).map(|i| u32_at(&bytes[ids.clone()], i));
+    let digest = fingerprint(&config, (0..num_merges).map(by_id), external);
+    if let Some(stored) = stored.filter(|&stored| stored != digest) {
+        return Err(TokenizerError::FingerprintMismatch {
+            expected: to_hex(stored.try_into().unwrap()),
+            found: to_hex(&digest),
+        });
+    }
+
+    let tables = Tables {
+        merges,
+        merge_order,
+        ids,
+        id_order,
+        fingerprint: digest,
+    };
+    Ok((config, tables))
+}
+
+fn push_u32(out: &mut Vec<u8>, n: u32) {
+    out.extend_from_slice(&n.to_le_bytes());
+}
+
+impl BPETokenizer {
+    /// serializes to the compact binary format, see `from_bytes`
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut out = Vec::with_capacity(64 + 16 * self.encoder.len());
+        out.extend_from_slice(MAGIC);
+        push_u32(&mut out, BINARY_VERSION);
+        out.extend_from_slice(&self.digest());
+
+        let mut config = self.config.clone();
+        let specials = config.special_tokens_map.take().unwrap_or_default();
+        let config = serde_json::to_vec(&config)?;
+        push_u32(&mut out, config.len() as u32);
+        out.extend_from_slice(&config);
+
+        let mut specials: Vec<_> = specials.into_iter().map(|(token, id)| (id, token)).collect();
+        specials.sort_unstable();
+        push_u32(&mut out, specials.len() as u32);
+        for (id, token) in specials {
+            push_u32(&mut out, id);
+            push_u32(&mut out, token.len() as u32);
+            out.extend_from_slice(token.as_bytes());
+        }
+
+        let mut merges: Vec<_> = self.encoder.iter().map(|(&pair, &id)| (pair, id)).collect();
+        merges.sort_unstable();
+        push_u32(&mut out, merges.len() as u32);
+        for &((l, r), id) in merges.iter() {
+            push_u32(&mut out, l);
+            push_u32(&mut out, r);
+            push_u32(&mut out, id);
+        }
+        let mut order: Vec<usize> = (0..merges.len()).collect();
+        order.sort_unstable_by_key(|&i| merges[i].1);
+        order.into_iter().for_each(|i| push_u32(&mut out, i as u32));
+
+        let ids = self.ids.as_ref().map_or(&[][..], |ids| ids.as_slice());
+        push_u32(&mut out, ids.len() as u32);
+        ids.iter().for_each(|&id| push_u32(&mut out, id));
+        let mut order: Vec<usize> = (0..ids.len()).collect();
+        order.sort_unstable_by_key(|&i| (ids[i], i));
+        order.into_iter().for_each(|i| push_u32(&mut out, i as u32));
+
+        Ok(out)
+    }
+
+    /// reads a tokenizer written by `to_bytes`. malformed input is reported as
+    /// `TokenizerError::Corrupt`, never a panic, and tables that don't match
+    /// the stored fingerprint as `TokenizerError::FingerprintMismatch`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let (config, tables) = parse(bytes)?;
+
+        let mut encoder = FwdMap::default();
+        for merge in bytes[tables.merges].chunks_exact(12) {
+            let (l, r, id) = (u32_at(merge, 0), u32_at(merge, 1), u32_at(merge, 2));
+            if encoder.insert((l, r), id).is_some() {
+                return corrupt(format!("duplicate merge ({}, {})", l, r));
+            }
+        }
+
+        let ids: Vec<Token> = bytes[tables.ids].chunks_exact(4).map(|id| u32_at(id, 0)).collect();
+
+        Ok(BPETokenizer {
+            encoder,
+            decoder: RwLock::new(None),
+            config,
+            ids: (!ids.is_empty()).then(|| IdMap::new(ids)),
+            specials: RwLock::new(None),
+        })
+    }
+
+    pub fn save_binary<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        Ok(write(path, self.to_bytes()?)?)
+    }
+
+    pub fn from_binary<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::from_bytes(&read(path)?)
+    }
+}
+
+impl BPETokenizer {
+    /// like `save_pretrained`, except paths ending in `.tkn` use the binary format
+    pub fn save_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        match is_binary(path.as_ref()) {
+            true => self.save_binary(path),
+            false => self.save_pretrained(path),
+        }
+    }
+
+    /// loads either format written by `save_file`. unlike `from_pretrained`, an
+    /// unsupported version or a fingerprint mismatch is reported as its own error
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        match is_binary(path.as_ref()) {
+            true => Self::from_binary(path),
+            false => tokenizer_from_str(&read_to_string(path)?),
+        }
+    }
+}
+
+fn is_binary(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == BINARY_EXTENSION)
+}
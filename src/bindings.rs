@@ -1,21 +1,73 @@
+use pyo3::exceptions::{PyIOError, PyRuntimeError, PyTypeError, PyValueError};
 use pyo3::prelude::*;
 
+use crate::error::TokenizerError;
+
+impl From<TokenizerError> for PyErr {
+    fn from(e: TokenizerError) -> Self {
+        match e {
+            TokenizerError::Io(_) => PyIOError::new_err(e.to_string()),
+            TokenizerError::PreTokenizer(_)
+            | TokenizerError::ThreadPool(_)
+            | TokenizerError::Interrupted => {
+                PyRuntimeError::new_err(e.to_string())
+            }
+            TokenizerError::Serialization(_)
+            | TokenizerError::Utf8(_)
+            | TokenizerError::UnknownToken(_)
+            | TokenizerError::CheckpointMismatch
+            | TokenizerError::Unsupported(_)
+            | TokenizerError::Corrupt(_)
+            | TokenizerError::UnsupportedVersion { .. }
+            | TokenizerError::FingerprintMismatch { .. }
+            | TokenizerError::DisallowedSpecial(_)
+            | TokenizerError::InvalidArgument(_) => PyValueError::new_err(e.to_string()),
+        }
+    }
+}
+
 #[pymodule]
 #[pyo3(name = "toktkn")]
 mod tkn {
-    use pyo3::types::PyType;
+    use pyo3::call::PyCallArgs;
+    use pyo3::types::{PyBytes, PyIterator, PyType};
+    use std::collections::HashMap;
+    use std::ops::ControlFlow;
+    use std::path::Path;
 
     use super::*;
-    use crate::{Token, Tokenizer, Pretrained, FwdMap};
+    use crate::{Pretrained, Token, Tokenizer, FwdMap};
 
     use crate::config::TokenizerConfig as _TokenizerConfig;
     use crate::preproc::Normalizer as _Normalizer;
+    use crate::pretokenizer::PreTokenizer as _PreTokenizer;
+    use crate::stream::Utf8Buffer;
     use crate::BPETokenizer as _BPETokenizer;
+    use crate::MappedBPETokenizer as _MappedBPETokenizer;
+    use crate::observer::{SilentObserver, TqdmObserver, TrainingObserver};
+    use crate::tokenizer::check_interval;
+    use crate::{StopReason, TrainingReport as _TrainingReport};
+    use crate::{EncodeOptions, SpecialTokenSet};
 
     #[pyclass]
     #[derive(Clone)]
     struct Normalizer(_Normalizer);
 
+    #[pyclass]
+    #[derive(Clone)]
+    struct PreTokenizer(_PreTokenizer);
+
+    #[pymethods]
+    impl PreTokenizer {
+        #[new]
+        fn new(name: &str) -> PyResult<Self> {
+            let pretokenizer = name
+                .parse::<_PreTokenizer>()
+                .map_err(PyValueError::new_err)?;
+            Ok(PreTokenizer(pretokenizer))
+        }
+    }
+
     #[pyclass]
     #[derive(Clone)]
     struct TokenizerConfig(_TokenizerConfig);
@@ -23,16 +75,30 @@ mod tkn {
     #[pymethods]
     impl TokenizerConfig {
         #[new]
-        #[pyo3(signature=(vocab_size, preproc=None, /))] // '/' each param before is positional-only
-        fn new(vocab_size: usize, preproc: Option<Normalizer>) -> Self {
-            TokenizerConfig(_TokenizerConfig::new(
+        #[pyo3(signature=(vocab_size, preproc=None, /, pretokenizer=None, num_threads=None, min_frequency=None, max_token_length=None))] // '/' each param before is positional-only
+        fn new(
+            vocab_size: usize,
+            preproc: Option<Normalizer>,
+            pretokenizer: Option<PreTokenizer>,
+            num_threads: Option<usize>,
+            min_frequency: Option<u64>,
+            max_token_length: Option<usize>,
+        ) -> PyResult<Self> {
+            let mut config = _TokenizerConfig::try_new(
                 vocab_size,
                 preproc.map(|x| x.0),
-            ))
+            )?;
+            if let Some(pretokenizer) = pretokenizer {
+                config.pretokenizer = pretokenizer.0;
+            }
+            config.num_threads = num_threads;
+            config.min_frequency = min_frequency;
+            config.max_token_length = max_token_length;
+            Ok(TokenizerConfig(config))
         }
         #[classmethod]
         pub fn from_pretrained(_cls: &Bound<'_, PyType>, path: &str) -> PyResult<Self> {
-            let config = _TokenizerConfig::from_pretrained(path)?;
+            let config = _TokenizerConfig::from_file(path)?;
             Ok(TokenizerConfig(config))
         }
 
@@ -41,6 +107,137 @@ mod tkn {
         }
     }
 
+    #[pyclass]
+    #[derive(Clone)]
+    struct TrainingReport(_TrainingReport);
+
+    /// `"all"` or any iterable of special token strings, like tiktoken takes
+    fn special_token_set(tokens: &Bound<'_, PyAny>) -> PyResult<SpecialTokenSet> {
+        if let Ok(name) = tokens.extract::<String>() {
+            return match name.as_str() {
+                "all" => Ok(SpecialTokenSet::All),
+                _ => Err(PyValueError::new_err(format!(
+                    "expected \"all\" or a collection of special tokens, got {:?}",
+                    name
+                ))),
+            };
+        }
+        let tokens = tokens
+            .try_iter()?
+            .map(|token| token?.extract::<String>())
+            .collect::<PyResult<Vec<_>>>()?;
+        Ok(SpecialTokenSet::only(tokens))
+    }
+
+    fn encode_options(
+        allowed_special: Option<&Bound<'_, PyAny>>,
+        disallowed_special: Option<&Bound<'_, PyAny>>,
+    ) -> PyResult<EncodeOptions> {
+        let mut options = EncodeOptions::default();
+        if let Some(allowed) = allowed_special {
+            options.allowed_special = special_token_set(allowed)?;
+        }
+        if let Some(disallowed) = disallowed_special {
+            options.disallowed_special = special_token_set(disallowed)?;
+        }
+        Ok(options)
+    }
+
+    /// forwards training events to the python object's `on_merge`,
+    /// `on_progress` and `on_finish` methods, whichever it defines
+    struct PyObserver<'py> {
+        observer: Bound<'py, PyAny>,
+        // the first exception raised by a callback, later events are dropped
+        err: Option<PyErr>,
+    }
+
+    impl<'py> PyObserver<'py> {
+        // stops training once a callback raised
+        fn call(&mut self, name: &str, args: impl PyCallArgs<'py>) -> ControlFlow<()> {
+            if self.err.is_none() {
+                let result = match self.observer.hasattr(name) {
+                    Ok(true) => self.observer.call_method1(name, args).map(|_| ()),
+                    Ok(false) => Ok(()),
+                    Err(e) => Err(e),
+                };
+                self.err = result.err();
+            }
+            match self.err {
+                Some(_) => ControlFlow::Break(()),
+                None => ControlFlow::Continue(()),
+            }
+        }
+    }
+
+    impl TrainingObserver for PyObserver<'_> {
+        fn on_merge(&mut self, pair: (Token, Token), token: Token, count: u64) -> ControlFlow<()> {
+            self.call("on_merge", (pair, token, count))
+        }
+
+        fn on_progress(&mut self, done: usize, total: usize) -> ControlFlow<()> {
+            self.call("on_progress", (done, total))
+        }
+
+        fn on_finish(&mut self, report: &_TrainingReport) -> ControlFlow<()> {
+            self.call("on_finish", (TrainingReport(report.clone()),))
+        }
+    }
+
+    fn with_observer<R>(
+        observer: Option<&Bound<'_, PyAny>>,
+        verbose: bool,
+        train: impl FnOnce(&mut dyn TrainingObserver) -> crate::error::Result<R>,
+    ) -> PyResult<R> {
+        match observer {
+            Some(observer) => {
+                let mut observer = PyObserver { observer: observer.clone(), err: None };
+                let result = train(&mut observer);
+                // the exception that stopped training, rather than `Interrupted`
+                match observer.err {
+                    Some(e) => Err(e),
+                    None => Ok(result?),
+                }
+            }
+            None if verbose => Ok(train(&mut TqdmObserver::default())?),
+            None => Ok(train(&mut SilentObserver)?),
+        }
+    }
+
+    // strings of a python iterable, stopping at the first item that raises or
+    // isn't a str. the exception is left in `err`
+    fn py_texts<'a, 'py: 'a>(
+        iterator: &Bound<'py, PyAny>,
+        err: &'a mut Option<PyErr>,
+    ) -> PyResult<impl Iterator<Item = String> + 'a> {
+        Ok(iterator.try_iter()?.map_while(move |item| {
+            match item.and_then(|i| i.extract::<String>()) {
+                Ok(text) => Some(text),
+                Err(e) => {
+                    *err = Some(e);
+                    None
+                }
+            }
+        }))
+    }
+
+    #[pymethods]
+    impl TrainingReport {
+        /// merges learned in this run as ((left, right), id), in order
+        #[getter]
+        pub fn merges(&self) -> Vec<((Token, Token), Token)> {
+            self.0.merges.clone()
+        }
+
+        #[getter]
+        pub fn stop_reason(&self) -> &'static str {
+            match self.0.stop_reason {
+                StopReason::VocabSizeReached => "vocab_size_reached",
+                StopReason::NoPairsLeft => "no_pairs_left",
+                StopReason::BelowMinFrequency => "below_min_frequency",
+            }
+        }
+    }
+
     #[pyclass]
     struct BPETokenizer(_BPETokenizer);
 
@@ -55,6 +252,10 @@ mod tkn {
             self.0.len()
         }
 
+        pub fn fingerprint(&self) -> String{
+            self.0.fingerprint()
+        }
+
         #[getter]
         pub fn encoder(&self)->PyResult<FwdMap>{
             Ok(self.0.encoder.clone())
@@ -66,16 +267,163 @@ mod tkn {
             text
         }
 
-        pub fn train(&mut self, text: &str) -> Vec<Token>{
-            self.0.train(text)
+        /// `observer` may define any of `on_merge(pair, token, count)`,
+        /// `on_progress(done, total)` and `on_finish(report)`. without one a
+        /// progress bar is drawn unless `verbose` is false. when `checkpoint` is
+        /// given progress is saved there every `checkpoint_interval` merges.
+        #[pyo3(signature=(text, observer=None, verbose=true, checkpoint=None, checkpoint_interval=1000))]
+        pub fn train(
+            &mut self,
+            text: &str,
+            observer: Option<&Bound<'_, PyAny>>,
+            verbose: bool,
+            checkpoint: Option<String>,
+            checkpoint_interval: usize,
+        ) -> PyResult<TrainingReport>{
+            let report = with_observer(observer, verbose, |o| match &checkpoint {
+                Some(path) => self.0.try_train_with_checkpoints(text, path, checkpoint_interval, o),
+                None => self.0.try_train_with_observer(text, o),
+            })?;
+            Ok(TrainingReport(report))
+        }
+
+        /// learns `extra_tokens` more merges from `corpus`, keeping every existing id
+        #[pyo3(signature=(corpus, extra_tokens, observer=None, verbose=true))]
+        pub fn extend(
+            &mut self,
+            corpus: &str,
+            extra_tokens: usize,
+            observer: Option<&Bound<'_, PyAny>>,
+            verbose: bool,
+        ) -> PyResult<TrainingReport> {
+            let report = with_observer(observer, verbose, |o| {
+                self.0.extend_with_observer(corpus, extra_tokens, o)
+            })?;
+            Ok(TrainingReport(report))
+        }
+
+        /// continues an interrupted `train` call, returns the tokenizer and its report
+        #[classmethod]
+        #[pyo3(signature=(checkpoint, corpus, observer=None, verbose=true))]
+        pub fn resume_training(
+            _cls: &Bound<'_, PyType>,
+            checkpoint: &str,
+            corpus: &str,
+            observer: Option<&Bound<'_, PyAny>>,
+            verbose: bool,
+        ) -> PyResult<(Self, TrainingReport)> {
+            let (tokenizer, report) = with_observer(observer, verbose, |o| {
+                _BPETokenizer::resume_training_with_observer(checkpoint, corpus, o)
+            })?;
+            Ok((BPETokenizer(tokenizer), TrainingReport(report)))
+        }
+
+        /// trains on any iterable of strings without loading it all in memory,
+        /// `checkpoint` works as in `train`
+        #[pyo3(signature=(iterator, observer=None, verbose=true, checkpoint=None, checkpoint_interval=1000))]
+        pub fn train_from_iterator(
+            &mut self,
+            iterator: &Bound<'_, PyAny>,
+            observer: Option<&Bound<'_, PyAny>>,
+            verbose: bool,
+            checkpoint: Option<String>,
+            checkpoint_interval: usize,
+        ) -> PyResult<TrainingReport>{
+            if checkpoint.is_some() {
+                check_interval(checkpoint_interval)?;
+            }
+            let mut err = None;
+            let counts = self.0._count_from_iterator(py_texts(iterator, &mut err)?.map(Ok))?;
+
+            // a failing iterator leaves the tokenizer untouched
+            if let Some(e) = err {
+                return Err(e);
+            }
+            let checkpoint = checkpoint.as_deref().map(|path| (Path::new(path), checkpoint_interval));
+            let report = with_observer(observer, verbose, |o| self.0._train_on_counts(counts, o, checkpoint))?;
+            Ok(TrainingReport(report))
+        }
+
+        #[pyo3(signature=(paths, observer=None, verbose=true, checkpoint=None, checkpoint_interval=1000))]
+        pub fn train_from_files(
+            &mut self,
+            paths: Vec<String>,
+            observer: Option<&Bound<'_, PyAny>>,
+            verbose: bool,
+            checkpoint: Option<String>,
+            checkpoint_interval: usize,
+        ) -> PyResult<TrainingReport>{
+            let report = with_observer(observer, verbose, |o| match &checkpoint {
+                Some(path) => self.0.train_from_files_with_checkpoints(&paths, path, checkpoint_interval, o),
+                None => self.0.train_from_files_with_observer(&paths, o),
+            })?;
+            Ok(TrainingReport(report))
         }
 
-        pub fn encode(&mut self, text: &str) -> Vec<Token>{
-            self.0.encode(text)
+        /// continues an interrupted `train_from_iterator` call on the same texts
+        #[classmethod]
+        #[pyo3(signature=(checkpoint, iterator, observer=None, verbose=true))]
+        pub fn resume_training_from_iterator(
+            _cls: &Bound<'_, PyType>,
+            checkpoint: &str,
+            iterator: &Bound<'_, PyAny>,
+            observer: Option<&Bound<'_, PyAny>>,
+            verbose: bool,
+        ) -> PyResult<(Self, TrainingReport)> {
+            let mut err = None;
+            let texts = py_texts(iterator, &mut err)?;
+            let result = with_observer(observer, verbose, |o| {
+                _BPETokenizer::resume_training_from_iterator(checkpoint, texts, o)
+            });
+            // rather than the mismatch a failing iterator causes
+            if let Some(e) = err {
+                return Err(e);
+            }
+            let (tokenizer, report) = result?;
+            Ok((BPETokenizer(tokenizer), TrainingReport(report)))
+        }
+
+        /// continues an interrupted `train_from_files` call on the same files
+        #[classmethod]
+        #[pyo3(signature=(checkpoint, paths, observer=None, verbose=true))]
+        pub fn resume_training_from_files(
+            _cls: &Bound<'_, PyType>,
+            checkpoint: &str,
+            paths: Vec<String>,
+            observer: Option<&Bound<'_, PyAny>>,
+            verbose: bool,
+        ) -> PyResult<(Self, TrainingReport)> {
+            let (tokenizer, report) = with_observer(observer, verbose, |o| {
+                _BPETokenizer::resume_training_from_files(checkpoint, &paths, o)
+            })?;
+            Ok((BPETokenizer(tokenizer), TrainingReport(report)))
         }
 
-        pub fn decode(&mut self, ids: Vec<Token>) -> String{
-            self.0.decode(&ids)
+        #[pyo3(signature=(text, allowed_special=None, disallowed_special=None))]
+        pub fn encode(
+            &mut self,
+            text: &str,
+            allowed_special: Option<&Bound<'_, PyAny>>,
+            disallowed_special: Option<&Bound<'_, PyAny>>,
+        ) -> PyResult<Vec<Token>>{
+            let options = encode_options(allowed_special, disallowed_special)?;
+            Ok(self.0.try_encode_with(text, &options)?)
+        }
+
+        pub fn decode(&mut self, ids: Vec<Token>) -> PyResult<String>{
+            Ok(self.0.try_decode(&ids)?)
+        }
+
+        pub fn decode_bytes<'py>(&self, py: Python<'py>, ids: Vec<Token>) -> PyResult<Bound<'py, PyBytes>>{
+            Ok(PyBytes::new(py, &self.0.decode_bytes(&ids)?))
+        }
+
+        pub fn decode_lossy(&self, ids: Vec<Token>) -> String{
+            self.0.decode_lossy(&ids)
+        }
+
+        pub fn id_to_bytes<'py>(&self, py: Python<'py>, id: Token) -> Option<Bound<'py, PyBytes>>{
+            self.0.id_to_bytes(id).map(|b| PyBytes::new(py, &b))
         }
 
         pub fn add_special_tokens(&mut self, special_tokens: Vec<String>){
@@ -84,12 +432,161 @@ mod tkn {
 
         #[classmethod]
         pub fn from_pretrained(_cls: &Bound<'_, PyType>, path: &str) -> PyResult<Self> {
-            let bpe = _BPETokenizer::from_pretrained(path)?;
+            let bpe = _BPETokenizer::from_file(path)?;
+            Ok(BPETokenizer(bpe))
+        }
+
+        /// loads a byte-level BPE model from a HuggingFace `tokenizer.json`
+        #[classmethod]
+        pub fn from_hf(_cls: &Bound<'_, PyType>, path: &str) -> PyResult<Self> {
+            Ok(BPETokenizer(_BPETokenizer::from_hf(path)?))
+        }
+
+        /// writes a HuggingFace `tokenizer.json` encoding to the same ids
+        pub fn save_hf(&self, path: &str) -> PyResult<()> {
+            Ok(self.0.save_hf(path)?)
+        }
+
+        /// loads a GPT-2 style `vocab.json` and `merges.txt`, `special_tokens`
+        /// lists the vocab entries that are special tokens
+        #[classmethod]
+        #[pyo3(signature=(vocab, merges, special_tokens=None))]
+        pub fn from_vocab_merges(
+            _cls: &Bound<'_, PyType>,
+            vocab: &str,
+            merges: &str,
+            special_tokens: Option<Vec<String>>,
+        ) -> PyResult<Self> {
+            let special_tokens = special_tokens.unwrap_or_default();
+            let special_tokens: Vec<&str> = special_tokens.iter().map(String::as_str).collect();
+            Ok(BPETokenizer(_BPETokenizer::from_vocab_merges(vocab, merges, &special_tokens)?))
+        }
+
+        /// writes a GPT-2 style `vocab.json` and `merges.txt` encoding to the same ids
+        pub fn save_vocab_merges(&self, vocab: &str, merges: &str) -> PyResult<()> {
+            Ok(self.0.save_vocab_merges(vocab, merges)?)
+        }
+
+        /// loads a tiktoken `.tiktoken` rank file, `special_tokens` maps each
+        /// special token to its id
+        #[classmethod]
+        #[pyo3(signature=(path, pretokenizer=None, special_tokens=None))]
+        pub fn from_tiktoken(
+            _cls: &Bound<'_, PyType>,
+            path: &str,
+            pretokenizer: Option<PreTokenizer>,
+            special_tokens: Option<HashMap<String, Token>>,
+        ) -> PyResult<Self> {
+            let mut special_tokens: Vec<(String, Token)> =
+                special_tokens.unwrap_or_default().into_iter().collect();
+            special_tokens.sort_unstable_by_key(|&(_, id)| id);
+            let special_tokens: Vec<(&str, Token)> =
+                special_tokens.iter().map(|(s, id)| (s.as_str(), *id)).collect();
+
+            let pretokenizer = pretokenizer.map_or(_PreTokenizer::Cl100k, |p| p.0);
+            let bpe = _BPETokenizer::from_tiktoken(path, pretokenizer, &special_tokens)?;
             Ok(BPETokenizer(bpe))
         }
 
+        /// writes a tiktoken `.tiktoken` rank file, without the special tokens
+        pub fn save_tiktoken(&self, path: &str) -> PyResult<()> {
+            Ok(self.0.save_tiktoken(path)?)
+        }
+
         pub fn save_pretrained(&mut self, path: &str) -> PyResult<()>{
-            Ok(self.0.save_pretrained(path)?)
+            Ok(self.0.save_file(path)?)
+        }
+    }
+
+    /// read-only tokenizer over a memory-mapped `.tkn` file, see `BPETokenizer.save_pretrained`
+    #[pyclass(frozen)]
+    struct MappedBPETokenizer(_MappedBPETokenizer);
+
+    #[pymethods]
+    impl MappedBPETokenizer {
+        #[new]
+        pub fn new(path: &str) -> PyResult<Self> {
+            Ok(MappedBPETokenizer(_MappedBPETokenizer::open(path)?))
+        }
+
+        pub fn __len__(&self) -> usize{
+            self.0.len()
+        }
+
+        pub fn fingerprint(&self) -> String{
+            self.0.fingerprint()
+        }
+
+        #[pyo3(signature=(text, allowed_special=None, disallowed_special=None))]
+        pub fn encode(
+            &self,
+            text: &str,
+            allowed_special: Option<&Bound<'_, PyAny>>,
+            disallowed_special: Option<&Bound<'_, PyAny>>,
+        ) -> PyResult<Vec<Token>>{
+            let options = encode_options(allowed_special, disallowed_special)?;
+            Ok(self.0.try_encode_with(text, &options)?)
+        }
+
+        pub fn decode(&self, ids: Vec<Token>) -> PyResult<String>{
+            Ok(self.0.try_decode(&ids)?)
+        }
+
+        pub fn decode_bytes<'py>(&self, py: Python<'py>, ids: Vec<Token>) -> PyResult<Bound<'py, PyBytes>>{
+            Ok(PyBytes::new(py, &self.0.decode_bytes(&ids)?))
+        }
+
+        pub fn decode_lossy(&self, ids: Vec<Token>) -> String{
+            self.0.decode_lossy(&ids)
+        }
+
+        pub fn id_to_bytes<'py>(&self, py: Python<'py>, id: Token) -> Option<Bound<'py, PyBytes>>{
+            self.0.id_to_bytes(id).map(|b| PyBytes::new(py, &b))
+        }
+    }
+
+    /// streams text out of ids, either by calling `step` for each new id or by
+    /// iterating over the decoder when built from an iterable of ids
+    #[pyclass]
+    struct StreamDecoder {
+        tokenizer: Py<BPETokenizer>,
+        buffer: Utf8Buffer,
+        ids: Option<Py<PyIterator>>,
+    }
+
+    #[pymethods]
+    impl StreamDecoder {
+        #[new]
+        #[pyo3(signature=(tokenizer, ids=None))]
+        fn new(tokenizer: Py<BPETokenizer>, ids: Option<&Bound<'_, PyAny>>) -> PyResult<Self> {
+            let ids = ids.map(|i| i.try_iter().map(|i| i.unbind())).transpose()?;
+            Ok(StreamDecoder { tokenizer, buffer: Utf8Buffer::default(), ids })
+        }
+
+        pub fn step(&mut self, py: Python<'_>, id: Token) -> PyResult<Option<String>>{
+            let tokenizer = self.tokenizer.borrow(py);
+            let bytes = tokenizer.0.id_to_bytes(id).ok_or(TokenizerError::UnknownToken(id))?;
+            Ok(self.buffer.push(&bytes))
+        }
+
+        pub fn flush(&mut self) -> Option<String>{
+            self.buffer.flush()
+        }
+
+        fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+            slf
+        }
+
+        fn __next__(&mut self, py: Python<'_>) -> PyResult<Option<String>> {
+            let Some(ids) = self.ids.as_ref().map(|i| i.clone_ref(py)) else {
+                return Err(PyTypeError::new_err("StreamDecoder was built without ids to iterate over"));
+            };
+            for id in ids.bind(py).clone() {
+                if let Some(text) = self.step(py, id?.extract()?)? {
+                    return Ok(Some(text));
+                }
+            }
+            Ok(self.flush())
         }
     }
 }
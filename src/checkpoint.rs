@@ -0,0 +1,52 @@
+use serde::{Deserialize, Serialize, Serializer};
+use std::fs::{rename, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use crate::error::Result;
+use crate::tokenizer::BPETokenizer;
+use crate::trainer::WordCounts;
+
+/// Snapshot of an interrupted training run: the merges learned so far and the
+/// pre-token counts of the corpus. Loaded through `Pretrained::from_pretrained`.
+#[derive(Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub tokenizer: BPETokenizer,
+    /// merges between two checkpoints
+    pub interval: usize,
+    #[serde(serialize_with = "serialize_counts")]
+    pub(crate) counts: WordCounts,
+}
+
+// same layout as `Checkpoint`, written mid training without cloning the tokenizer
+#[derive(Serialize)]
+pub(crate) struct CheckpointRef<'a> {
+    pub tokenizer: &'a BPETokenizer,
+    pub interval: usize,
+    #[serde(serialize_with = "serialize_counts")]
+    pub counts: &'a WordCounts,
+}
+
+impl CheckpointRef<'_> {
+    /// writes to a temporary file first so a crash never leaves a truncated checkpoint
+    pub fn save(&self, path: &Path) -> Result<()> {
+        // next to `path` under its full name, `bpe.ckpt` and `bpe.json` mustn't share `bpe.tmp`
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        let tmp = PathBuf::from(tmp);
+
+        let mut writer = BufWriter::new(File::create(&tmp)?);
+        serde_json::to_writer(&mut writer, self)?;
+        writer.flush()?;
+        drop(writer);
+
+        rename(tmp, path)?;
+        Ok(())
+    }
+}
+
+fn serialize_counts<S: Serializer>(counts: &WordCounts, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+    let mut entries: Vec<_> = counts.iter().collect();
+    entries.sort_unstable();
+    serializer.collect_map(entries)
+}
@@ -1,26 +1,64 @@
 use crate::preproc::Normalizer;
+use crate::pretokenizer::PreTokenizer;
+use crate::error::{Result, TokenizerError};
+use crate::schema::{config_from_value, required, serialize_vocab_map};
 use crate::tokenizer::VocabMap;
 use serde::{Deserialize, Serialize};
+use std::fs::read_to_string;
+use std::path::Path;
 
 
+// the derived impls only cover the fields, `schema` wraps them with a
+// `format_version` and migrates older layouts on the way in
 #[derive(Serialize, Deserialize, Clone)]
+#[serde(remote = "Self")]
 pub struct TokenizerConfig {
     pub vocab_size: usize,
+    #[serde(serialize_with = "serialize_vocab_map", deserialize_with = "required")]
     pub special_tokens_map: Option<VocabMap>,
-    #[serde(default)]
     pub preproc: Normalizer,
+    pub pretokenizer: PreTokenizer,
+    /// threads used for training, defaults to rayon's global pool
+    #[serde(deserialize_with = "required")]
+    pub num_threads: Option<usize>,
+    /// pairs seen fewer times than this are never merged, training stops early instead
+    #[serde(deserialize_with = "required")]
+    pub min_frequency: Option<u64>,
+    /// longest token in bytes training is allowed to create
+    #[serde(deserialize_with = "required")]
+    pub max_token_length: Option<usize>,
 }
 
 impl TokenizerConfig {
+    /// like `from_pretrained`, but an unsupported `format_version` is reported as
+    /// `TokenizerError::UnsupportedVersion` rather than a serialization error
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        config_from_value(serde_json::from_str(&read_to_string(path)?)?)
+    }
+
+    /// like `try_new` but panics on error
     pub fn new(vocab_size: usize, preproc: Option<Normalizer>) -> Self {
-        assert!(vocab_size > 0, "can't train on vocab_size <= 0!");
+        Self::try_new(vocab_size, preproc).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// fails with `TokenizerError::InvalidArgument` if `vocab_size` is 0
+    pub fn try_new(vocab_size: usize, preproc: Option<Normalizer>) -> Result<Self> {
+        if vocab_size == 0 {
+            return Err(TokenizerError::InvalidArgument(
+                "can't train on vocab_size <= 0".to_string(),
+            ));
+        }
 
         let preproc = preproc.unwrap_or_default();
 
-        Self {
+        Ok(Self {
             vocab_size,
             preproc,
             special_tokens_map: None,
-        }
+            pretokenizer: PreTokenizer::default(),
+            num_threads: None,
+            min_frequency: None,
+            max_token_length: None,
+        })
     }
 }
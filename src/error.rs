@@ -0,0 +1,122 @@
+use std::fmt;
+use std::string::FromUtf8Error;
+
+use crate::tokenizer::Token;
+
+pub type Result<T> = std::result::Result<T, TokenizerError>;
+
+#[derive(Debug)]
+pub enum TokenizerError {
+    /// reading or writing a pretrained file failed
+    Io(std::io::Error),
+    /// a pretrained file could not be (de)serialized
+    Serialization(serde_json::Error),
+    /// decoded bytes are not valid utf-8
+    Utf8(FromUtf8Error),
+    /// id is neither a byte, a merged token nor a special token
+    UnknownToken(Token),
+    /// the pre-tokenizer regex failed, e.g. by hitting its backtracking limit
+    PreTokenizer(Box<fancy_regex::Error>),
+    /// the training thread pool could not be started
+    ThreadPool(rayon::ThreadPoolBuildError),
+    /// training was resumed on a different corpus than the checkpoint was made from
+    CheckpointMismatch,
+    /// an imported file describes a model toktkn can't represent
+    Unsupported(String),
+    /// a tokenizer file is truncated or malformed, e.g. has ids that would break encoding
+    Corrupt(String),
+    /// a file was saved by a newer toktkn with a format this version can't read
+    UnsupportedVersion { found: u32, supported: u32 },
+    /// a saved tokenizer doesn't hash to the fingerprint recorded with it
+    FingerprintMismatch { expected: String, found: String },
+    /// text to encode contains a special token the caller disallowed
+    DisallowedSpecial(String),
+    /// a parameter is out of range, e.g. a zero `vocab_size`
+    InvalidArgument(String),
+    /// a `TrainingObserver` stopped training, the tokenizer was left as it was
+    Interrupted,
+}
+
+impl fmt::Display for TokenizerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TokenizerError::Io(e) => write!(f, "io error: {}", e),
+            TokenizerError::Serialization(e) => write!(f, "serialization error: {}", e),
+            TokenizerError::Utf8(e) => write!(f, "failed to decode into valid utf-8: {}", e),
+            TokenizerError::UnknownToken(t) => write!(f, "unknown token id: {}", t),
+            TokenizerError::PreTokenizer(e) => write!(f, "pre-tokenizer error: {}", e),
+            TokenizerError::ThreadPool(e) => write!(f, "thread pool error: {}", e),
+            TokenizerError::Unsupported(msg) => write!(f, "unsupported model: {}", msg),
+            TokenizerError::Corrupt(msg) => write!(f, "corrupt tokenizer file: {}", msg),
+            TokenizerError::UnsupportedVersion { found, supported } => write!(
+                f,
+                "file format version {} is newer than the supported version {}, upgrade toktkn to load it",
+                found, supported
+            ),
+            TokenizerError::InvalidArgument(msg) => write!(f, "invalid argument: {}", msg),
+            TokenizerError::DisallowedSpecial(token) => {
+                write!(f, "text contains the disallowed special token {:?}", token)
+            }
+            TokenizerError::FingerprintMismatch { expected, found } => write!(
+                f,
+                "tokenizer fingerprint {} doesn't match the {} it was saved with",
+                found, expected
+            ),
+            TokenizerError::CheckpointMismatch => {
+                write!(f, "corpus does not match the one the checkpoint was trained on")
+            }
+            TokenizerError::Interrupted => write!(f, "training was stopped by an observer"),
+        }
+    }
+}
+
+impl std::error::Error for TokenizerError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TokenizerError::Io(e) => Some(e),
+            TokenizerError::Serialization(e) => Some(e),
+            TokenizerError::Utf8(e) => Some(e),
+            TokenizerError::PreTokenizer(e) => Some(e.as_ref()),
+            TokenizerError::ThreadPool(e) => Some(e),
+            TokenizerError::UnknownToken(_)
+            | TokenizerError::CheckpointMismatch
+            | TokenizerError::Unsupported(_)
+            | TokenizerError::Corrupt(_)
+            | TokenizerError::UnsupportedVersion { .. }
+            | TokenizerError::FingerprintMismatch { .. }
+            | TokenizerError::DisallowedSpecial(_)
+            | TokenizerError::InvalidArgument(_)
+            | TokenizerError::Interrupted => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for TokenizerError {
+    fn from(e: std::io::Error) -> Self {
+        TokenizerError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for TokenizerError {
+    fn from(e: serde_json::Error) -> Self {
+        TokenizerError::Serialization(e)
+    }
+}
+
+impl From<FromUtf8Error> for TokenizerError {
+    fn from(e: FromUtf8Error) -> Self {
+        TokenizerError::Utf8(e)
+    }
+}
+
+impl From<fancy_regex::Error> for TokenizerError {
+    fn from(e: fancy_regex::Error) -> Self {
+        TokenizerError::PreTokenizer(Box::new(e))
+    }
+}
+
+impl From<rayon::ThreadPoolBuildError> for TokenizerError {
+    fn from(e: rayon::ThreadPoolBuildError) -> Self {
+        TokenizerError::ThreadPool(e)
+    }
+}
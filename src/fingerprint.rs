@@ -0,0 +1,87 @@
+use sha2::{Digest, Sha256};
+
+use crate::config::TokenizerConfig;
+use crate::tokenizer::{BPETokenizer, Token};
+
+/// raw sha-256 behind the hex fingerprint
+pub(crate) type Digest256 = [u8; 32];
+
+// bumped if what goes into the hash ever changes, so old and new
+// fingerprints can't collide by accident
+const DOMAIN: &[u8] = b"toktkn fingerprint 1";
+
+/// Hashes everything that decides how text is tokenized: merges and special
+/// tokens by id, the normalizer, the pre-tokenizer and imported ids. Training
+/// settings like `vocab_size` are left out, they don't change the output.
+///
+/// `merges` must be in id order, which makes the hash independent of how
+/// the tokenizer stores them.
+pub(crate) fn fingerprint(
+    config: &TokenizerConfig,
+    merges: impl ExactSizeIterator<Item = (Token, Token, Token)>,
+    ids: impl ExactSizeIterator<Item = Token>,
+) -> Digest256 {
+    let mut hasher = Sha256::new();
+    hasher.update(DOMAIN);
+
+    // names as serialized, so the hash doesn't depend on enum order. both
+    // are plain enums, serializing them can't fail
+    let preproc = serde_json::to_vec(&config.preproc).expect("normalizer serializes to json");
+    let pretokenizer = serde_json::to_vec(&config.pretokenizer).expect("pre-tokenizer serializes to json");
+    for part in [preproc, pretokenizer] {
+        hasher.update((part.len() as u32).to_le_bytes());
+        hasher.update(part);
+    }
+
+    hasher.update((merges.len() as u32).to_le_bytes());
+    for (l, r, id) in merges {
+        for n in [l, r, id] {
+            hasher.update(n.to_le_bytes());
+        }
+    }
+
+    let mut specials: Vec<_> = config
+        .special_tokens_map
+        .iter()
+        .flatten()
+        .map(|(token, &id)| (id, token))
+        .collect();
+    specials.sort_unstable();
+    hasher.update((specials.len() as u32).to_le_bytes());
+    for (id, token) in specials {
+        hasher.update(id.to_le_bytes());
+        hasher.update((token.len() as u32).to_le_bytes());
+        hasher.update(token.as_bytes());
+    }
+
+    hasher.update((ids.len() as u32).to_le_bytes());
+    for id in ids {
+        hasher.update(id.to_le_bytes());
+    }
+
+    hasher.finalize().into()
+}
+
+/// lowercase hex, as returned by `fingerprint`
+pub(crate) fn to_hex(digest: &Digest256) -> String {
+    digest.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+impl BPETokenizer {
+    /// Stable sha-256 of the merges, special tokens, normalizer and
+    /// pre-tokenizer, as lowercase hex. Two tokenizers with the same
+    /// fingerprint encode and decode identically.
+    ///
+    /// Saved files record it and `from_pretrained` checks it, so jobs can
+    /// compare fingerprints to assert they share a tokenizer.
+    pub fn fingerprint(&self) -> String {
+        to_hex(&self.digest())
+    }
+
+    pub(crate) fn digest(&self) -> Digest256 {
+        let mut merges: Vec<_> = self.encoder.iter().map(|(&(l, r), &id)| (l, r, id)).collect();
+        merges.sort_unstable_by_key(|&(_, _, id)| id);
+        let ids = self.ids.as_ref().map_or(&[][..], |ids| ids.as_slice());
+        fingerprint(&self.config, merges.into_iter(), ids.iter().copied())
+    }
+}
@@ -0,0 +1,188 @@
+use rustc_hash::FxHashMap;
+use std::fs::{read_to_string, File};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::config::TokenizerConfig;
+use crate::error::{Result, TokenizerError};
+use crate::ids::IdMap;
+use crate::pretokenizer::PreTokenizer;
+use crate::schema::serialize_vocab_map;
+use crate::tokenizer::{BPETokenizer, Token, VocabMap, BYTE_VOCAB_SIZE};
+
+fn unsupported<T>(msg: impl Into<String>) -> Result<T> {
+    Err(TokenizerError::Unsupported(msg.into()))
+}
+
+/// GPT-2's reversible mapping of bytes to printable characters, used by
+/// byte-level vocabularies to store arbitrary bytes as strings
+pub fn bytes_to_unicode() -> [char; 256] {
+    let mut chars = ['\0'; 256];
+    let mut n = 0;
+    for b in 0..=255u8 {
+        let printable = matches!(b, b'!'..=b'~' | 0xA1..=0xAC | 0xAE..=0xFF);
+        chars[b as usize] = if printable {
+            b as char
+        } else {
+            n += 1;
+            char::from_u32(255 + n).unwrap()
+        };
+    }
+    chars
+}
+
+/// builds a tokenizer from a byte-level vocab and its merges in priority order,
+/// `specials` become special tokens with the given ids
+pub(crate) fn from_byte_level(
+    vocab: &FxHashMap<String, Token>,
+    merges: Vec<(String, String)>,
+    specials: &[(String, Token)],
+) -> Result<BPETokenizer> {
+    // internal id of every token string so far, starting with the raw bytes
+    let mut internal: FxHashMap<String, Token> = FxHashMap::default();
+    let mut to_external = Vec::new();
+    for (b, c) in bytes_to_unicode().into_iter().enumerate() {
+        let token = c.to_string();
+        let Some(&id) = vocab.get(&token) else {
+            return unsupported(format!("byte {:#04x} is missing from the vocab", b));
+        };
+        internal.insert(token, b as Token);
+        to_external.push(id);
+    }
+
+    let mut tokenizer = BPETokenizer::new(TokenizerConfig::new(1, None));
+    for (left, right) in merges {
+        let (Some(&l), Some(&r)) = (internal.get(&left), internal.get(&right)) else {
+            return unsupported(format!("merge {:?} uses an unknown token", (left, right)));
+        };
+        let merged = left + &right;
+        let Some(&external) = vocab.get(&merged) else {
+            return unsupported(format!("merge result {:?} is missing from the vocab", merged));
+        };
+        // ranks are internal ids, a token can't be reached by two merges
+        if internal.contains_key(&merged) {
+            return unsupported(format!("token {:?} is produced by several merges", merged));
+        }
+
+        let id = (BYTE_VOCAB_SIZE + tokenizer.encoder.len()) as Token;
+        tokenizer.encoder.insert((l, r), id);
+        internal.insert(merged, id);
+        to_external.push(external);
+    }
+
+    if !specials.is_empty() {
+        let first = (BYTE_VOCAB_SIZE + tokenizer.encoder.len()) as Token;
+        let map: VocabMap = specials
+            .iter()
+            .enumerate()
+            .map(|(i, (token, _))| (token.clone(), first + i as Token))
+            .collect();
+        to_external.extend(specials.iter().map(|&(_, id)| id));
+        tokenizer.config.special_tokens_map = Some(map);
+    }
+
+    tokenizer.config.vocab_size = tokenizer.len();
+    tokenizer.ids = Some(IdMap::new(to_external));
+    Ok(tokenizer)
+}
+
+/// byte-level vocab of `tokenizer` with its special tokens, and its merges in
+/// priority order. fails if two merges produce the same bytes, as such
+/// vocabularies can't hold duplicate tokens.
+pub(crate) fn to_byte_level(tokenizer: &BPETokenizer) -> Result<(VocabMap, Vec<(String, String)>)> {
+    let chars = bytes_to_unicode();
+    let as_token = |bytes: &[u8]| -> String { bytes.iter().map(|&b| chars[b as usize]).collect() };
+
+    let mut vocab = VocabMap::default();
+    for b in 0..BYTE_VOCAB_SIZE as Token {
+        vocab.insert(as_token(&[b as u8]), tokenizer._external(b));
+    }
+
+    let mut merges: Vec<_> = tokenizer.encoder.iter().map(|(&pair, &id)| (pair, id)).collect();
+    merges.sort_unstable_by_key(|&(_, id)| id);
+
+    let mut byte_level_merges = Vec::with_capacity(merges.len());
+    for ((l, r), id) in merges {
+        let bytes = |id| tokenizer._id_to_bytes(id).ok_or(TokenizerError::UnknownToken(id));
+        let (left, right) = (as_token(&bytes(l)?), as_token(&bytes(r)?));
+
+        let merged = format!("{}{}", left, right);
+        if vocab.insert(merged.clone(), tokenizer._external(id)).is_some() {
+            return unsupported(format!("token {:?} is produced by several merges", merged));
+        }
+        byte_level_merges.push((left, right));
+    }
+
+    // listed in the vocab as well, like gpt2 does
+    for (token, &id) in tokenizer.config.special_tokens_map.iter().flatten() {
+        vocab.entry(token.clone()).or_insert(tokenizer._external(id));
+    }
+
+    Ok((vocab, byte_level_merges))
+}
+
+impl BPETokenizer {
+    /// loads a GPT-2 style `vocab.json` and `merges.txt`.
+    ///
+    /// the files don't mark special tokens, so they are passed in by their text,
+    /// e.g. gpt2's `<|endoftext|>`, and keep their vocab id. other vocab entries
+    /// no merge produces are ignored. text is split with the gpt2 pattern.
+    pub fn from_vocab_merges<P: AsRef<Path>, Q: AsRef<Path>>(
+        vocab: P,
+        merges: Q,
+        special_tokens: &[&str],
+    ) -> Result<Self> {
+        Self::from_vocab_merges_str(&read_to_string(vocab)?, &read_to_string(merges)?, special_tokens)
+    }
+
+    pub fn from_vocab_merges_str(vocab: &str, merges: &str, special_tokens: &[&str]) -> Result<Self> {
+        let vocab: FxHashMap<String, Token> = serde_json::from_str(vocab)?;
+
+        let mut pairs = Vec::new();
+        for line in merges.lines() {
+            // the first line is usually a `#version: 0.2` header
+            if line.is_empty() || line.starts_with("#version") {
+                continue;
+            }
+            match line.split_once(' ') {
+                Some((l, r)) => pairs.push((l.to_string(), r.to_string())),
+                None => return unsupported(format!("malformed merge {:?}", line)),
+            }
+        }
+
+        let mut specials = Vec::with_capacity(special_tokens.len());
+        for &token in special_tokens {
+            let Some(&id) = vocab.get(token) else {
+                return Err(TokenizerError::InvalidArgument(format!(
+                    "special token {:?} is missing from the vocab",
+                    token
+                )));
+            };
+            specials.push((token.to_string(), id));
+        }
+        specials.sort_unstable_by_key(|&(_, id)| id);
+        specials.dedup();
+
+        let mut tokenizer = from_byte_level(&vocab, pairs, &specials)?;
+        tokenizer.config.pretokenizer = PreTokenizer::Gpt2;
+        Ok(tokenizer)
+    }
+
+    /// writes a GPT-2 style `vocab.json` and `merges.txt` encoding to the same ids,
+    /// special tokens go in the vocab
+    pub fn save_vocab_merges<P: AsRef<Path>, Q: AsRef<Path>>(&self, vocab: P, merges: Q) -> Result<()> {
+        let (map, pairs) = to_byte_level(self)?;
+
+        let mut file = BufWriter::new(File::create(vocab)?);
+        serialize_vocab_map(&Some(map), &mut serde_json::Serializer::new(&mut file))?;
+        file.flush()?;
+
+        let mut file = BufWriter::new(File::create(merges)?);
+        writeln!(file, "#version: 0.2")?;
+        for (l, r) in pairs {
+            writeln!(file, "{} {}", l, r)?;
+        }
+        file.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,265 @@
+use rustc_hash::FxHashMap;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::fs::{read_to_string, File};
+use std::io::BufWriter;
+use std::path::Path;
+
+use crate::error::{Result, TokenizerError};
+use crate::gpt2::{from_byte_level, to_byte_level};
+use crate::pretokenizer::{
+    PreTokenizer, CL100K_PATTERN, DIGITS_PATTERN, GPT2_PATTERN, O200K_PATTERN,
+    PUNCTUATION_PATTERN, WHITESPACE_PATTERN,
+};
+use crate::tokenizer::{BPETokenizer, Token};
+
+// the parts of a HuggingFace `tokenizer.json` needed for byte-level BPE
+#[derive(Deserialize)]
+struct HfFile {
+    #[serde(default)]
+    added_tokens: Vec<HfAddedToken>,
+    normalizer: Option<Value>,
+    pre_tokenizer: Option<Value>,
+    model: HfModel,
+}
+
+#[derive(Deserialize)]
+struct HfAddedToken {
+    id: Token,
+    content: String,
+}
+
+#[derive(Deserialize)]
+struct HfModel {
+    #[serde(rename = "type")]
+    kind: Option<String>,
+    vocab: FxHashMap<String, Token>,
+    merges: Vec<HfMerge>,
+    dropout: Option<f32>,
+    continuing_subword_prefix: Option<String>,
+    end_of_word_suffix: Option<String>,
+    #[serde(default)]
+    byte_fallback: bool,
+    #[serde(default)]
+    ignore_merges: bool,
+}
+
+// merges are either "left right" or, in newer files, ["left", "right"]
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum HfMerge {
+    Joined(String),
+    Pair(String, String),
+}
+
+fn unsupported<T>(msg: impl Into<String>) -> Result<T> {
+    Err(TokenizerError::Unsupported(msg.into()))
+}
+
+impl BPETokenizer {
+    /// loads a byte-level BPE model from a HuggingFace `tokenizer.json`.
+    ///
+    /// `encode` returns the file's ids, added tokens become special tokens.
+    pub fn from_hf<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::from_hf_str(&read_to_string(path)?)
+    }
+
+    pub fn from_hf_str(json: &str) -> Result<Self> {
+        let file: HfFile = serde_json::from_str(json)?;
+        let model = file.model;
+
+        if model.kind.as_deref().is_some_and(|kind| kind != "BPE") {
+            return unsupported(format!("{} model, only BPE can be imported", model.kind.unwrap()));
+        }
+        if model.dropout.is_some_and(|p| p > 0.0) {
+            return unsupported("BPE dropout");
+        }
+        if model.byte_fallback {
+            return unsupported("byte fallback");
+        }
+        if model.ignore_merges {
+            return unsupported("ignore_merges");
+        }
+        let affix = |s: &Option<String>| s.as_deref().is_some_and(|s| !s.is_empty());
+        if affix(&model.continuing_subword_prefix) || affix(&model.end_of_word_suffix) {
+            return unsupported("subword prefixes and suffixes");
+        }
+        if file.normalizer.as_ref().is_some_and(|n| !n.is_null()) {
+            return unsupported("normalizers");
+        }
+        let pretokenizer = import_pre_tokenizer(file.pre_tokenizer.as_ref())?;
+
+        let mut merges = Vec::with_capacity(model.merges.len());
+        for merge in model.merges {
+            merges.push(match merge {
+                HfMerge::Joined(s) => match s.split_once(' ') {
+                    Some((l, r)) => (l.to_string(), r.to_string()),
+                    None => return unsupported(format!("malformed merge {:?}", s)),
+                },
+                HfMerge::Pair(l, r) => (l, r),
+            });
+        }
+        let specials: Vec<(String, Token)> =
+            file.added_tokens.into_iter().map(|t| (t.content, t.id)).collect();
+
+        let mut tokenizer = from_byte_level(&model.vocab, merges, &specials)?;
+        tokenizer.config.pretokenizer = pretokenizer;
+        Ok(tokenizer)
+    }
+
+    /// writes a HuggingFace `tokenizer.json` which encodes to the same ids.
+    ///
+    /// the config's normalizer is only ever applied through `preprocess`, so
+    /// none is written. fails if two merges produce the same bytes, as HF
+    /// vocabularies can't hold duplicate tokens.
+    pub fn save_hf<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let file = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(file, &self.to_hf_json()?)?;
+        Ok(())
+    }
+
+    pub fn to_hf_json(&self) -> Result<Value> {
+        let (vocab, merges) = to_byte_level(self)?;
+        let merges: Vec<String> = merges.into_iter().map(|(l, r)| format!("{} {}", l, r)).collect();
+
+        let mut specials: Vec<_> = self
+            .config
+            .special_tokens_map
+            .iter()
+            .flatten()
+            .map(|(token, &id)| (id, token))
+            .collect();
+        specials.sort_unstable();
+        let added_tokens: Vec<Value> = specials
+            .into_iter()
+            .map(|(id, token)| {
+                json!({
+                    "id": self._external(id),
+                    "content": token,
+                    "single_word": false,
+                    "lstrip": false,
+                    "rstrip": false,
+                    "normalized": false,
+                    "special": true,
+                })
+            })
+            .collect();
+
+        Ok(json!({
+            "version": "1.0",
+            "truncation": null,
+            "padding": null,
+            "added_tokens": added_tokens,
+            "normalizer": null,
+            "pre_tokenizer": export_pre_tokenizer(self.config.pretokenizer),
+            "post_processor": null,
+            "decoder": {
+                "type": "ByteLevel",
+                "add_prefix_space": false,
+                "trim_offsets": true,
+                "use_regex": true,
+            },
+            "model": {
+                "type": "BPE",
+                "dropout": null,
+                "unk_token": null,
+                "continuing_subword_prefix": null,
+                "end_of_word_suffix": null,
+                "fuse_unk": false,
+                "byte_fallback": false,
+                "ignore_merges": false,
+                "vocab": vocab,
+                "merges": merges,
+            },
+        }))
+    }
+}
+
+fn export_pre_tokenizer(pretokenizer: PreTokenizer) -> Value {
+    let byte_level = |use_regex: bool| {
+        json!({
+            "type": "ByteLevel",
+            "add_prefix_space": false,
+            "trim_offsets": true,
+            "use_regex": use_regex,
+        })
+    };
+    let split = |pattern: &str| {
+        json!({
+            "type": "Sequence",
+            "pretokenizers": [
+                {
+                    "type": "Split",
+                    "pattern": {"Regex": pattern},
+                    "behavior": "Isolated",
+                    "invert": false,
+                },
+                byte_level(false),
+            ],
+        })
+    };
+
+    match pretokenizer {
+        PreTokenizer::None => byte_level(false),
+        PreTokenizer::Gpt2 => byte_level(true),
+        PreTokenizer::Cl100k => split(CL100K_PATTERN),
+        PreTokenizer::O200k => split(O200K_PATTERN),
+        PreTokenizer::Whitespace => split(WHITESPACE_PATTERN),
+        PreTokenizer::Punctuation => split(PUNCTUATION_PATTERN),
+        PreTokenizer::Digits => split(DIGITS_PATTERN),
+    }
+}
+
+fn import_pre_tokenizer(pre_tokenizer: Option<&Value>) -> Result<PreTokenizer> {
+    let Some(pre_tokenizer) = pre_tokenizer.filter(|p| !p.is_null()) else {
+        return Ok(PreTokenizer::None);
+    };
+
+    match pre_tokenizer["type"].as_str() {
+        // splits with the gpt2 pattern itself
+        Some("ByteLevel") => {
+            byte_level(pre_tokenizer)?;
+            match pre_tokenizer["use_regex"].as_bool().unwrap_or(true) {
+                true => Ok(PreTokenizer::Gpt2),
+                false => Ok(PreTokenizer::None),
+            }
+        }
+        // a regex split followed by a byte-level pass that doesn't split again
+        Some("Sequence") => {
+            let steps = pre_tokenizer["pretokenizers"].as_array().map_or(&[][..], |s| s);
+            let [split, level] = steps else {
+                return unsupported("pre-tokenizer sequence other than Split then ByteLevel");
+            };
+            if split["type"] != "Split" || level["type"] != "ByteLevel" {
+                return unsupported("pre-tokenizer sequence other than Split then ByteLevel");
+            }
+            byte_level(level)?;
+            if level["use_regex"].as_bool().unwrap_or(true) {
+                return unsupported("byte-level pre-tokenizer splitting twice");
+            }
+            if split["behavior"] != "Isolated" || split["invert"].as_bool().unwrap_or(false) {
+                return unsupported("split behaviors other than Isolated");
+            }
+
+            match split["pattern"]["Regex"].as_str() {
+                Some(GPT2_PATTERN) => Ok(PreTokenizer::Gpt2),
+                Some(CL100K_PATTERN) => Ok(PreTokenizer::Cl100k),
+                Some(O200K_PATTERN) => Ok(PreTokenizer::O200k),
+                Some(WHITESPACE_PATTERN) => Ok(PreTokenizer::Whitespace),
+                Some(PUNCTUATION_PATTERN) => Ok(PreTokenizer::Punctuation),
+                Some(DIGITS_PATTERN) => Ok(PreTokenizer::Digits),
+                Some(pattern) => unsupported(format!("split pattern {}", pattern)),
+                None => unsupported("split on a literal string"),
+            }
+        }
+        Some(kind) => unsupported(format!("{} pre-tokenizer", kind)),
+        None => unsupported("pre-tokenizer without a type"),
+    }
+}
+
+fn byte_level(pre_tokenizer: &Value) -> Result<()> {
+    if pre_tokenizer["add_prefix_space"].as_bool().unwrap_or(false) {
+        return unsupported("add_prefix_space");
+    }
+    Ok(())
+}
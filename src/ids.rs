@@ -0,0 +1,66 @@
+use rustc_hash::FxHashMap;
+
+use crate::tokenizer::Token;
+
+/// Translates between internal ids and the ids of an imported vocabulary.
+///
+/// Internally raw bytes are always 0..255 and merged and special tokens follow
+/// in the order they were allocated, which is what merge ranks rely on.
+/// Imported vocabularies number their tokens however they like, so ids are
+/// translated on the way in and out of the tokenizer.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct IdMap {
+    // indexed by internal id
+    to_external: Vec<Token>,
+    to_internal: FxHashMap<Token, Token>,
+    // one past the largest external id, where `extend_to` allocates from
+    next: Token,
+}
+
+impl IdMap {
+    /// `to_external[i]` is the external id of internal id `i`
+    pub fn new(to_external: Vec<Token>) -> Self {
+        let mut to_internal = FxHashMap::default();
+        for (internal, &external) in to_external.iter().enumerate() {
+            // several internal ids may share an external one, the first decodes it
+            to_internal.entry(external).or_insert(internal as Token);
+        }
+        let next = to_external.iter().max().map_or(0, |&m| m + 1);
+        Self {
+            to_external,
+            to_internal,
+            next,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.to_external.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.to_external.is_empty()
+    }
+
+    pub fn external(&self, id: Token) -> Option<Token> {
+        self.to_external.get(id as usize).copied()
+    }
+
+    pub fn internal(&self, id: Token) -> Option<Token> {
+        self.to_internal.get(&id).copied()
+    }
+
+    /// external ids, indexed by internal id
+    pub fn as_slice(&self) -> &[Token] {
+        &self.to_external
+    }
+
+    /// maps internal ids up to `len` onto fresh external ids, past the largest one in use
+    pub fn extend_to(&mut self, len: usize) {
+        while self.to_external.len() < len {
+            let internal = self.to_external.len() as Token;
+            self.to_external.push(self.next);
+            self.to_internal.insert(self.next, internal);
+            self.next += 1;
+        }
+    }
+}
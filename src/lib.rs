@@ -1,14 +1,36 @@
 // modules
+pub mod binary;
+pub mod checkpoint;
 pub mod config;
+pub mod error;
+pub mod gpt2;
+pub mod huggingface;
+pub mod ids;
+pub mod mapped;
+pub mod observer;
 pub mod preproc;
+pub mod pretokenizer;
 pub mod pretrained;
+pub mod schema;
+pub mod special;
+pub mod stream;
+pub mod tiktoken;
 pub mod tokenizer;
 
 pub mod bindings;
 
+mod fingerprint;
+mod trainer;
 mod util;
 
 // re-exports
+pub use checkpoint::Checkpoint;
 pub use pretrained::Pretrained;
 pub use config::TokenizerConfig;
+pub use error::TokenizerError;
+pub use mapped::MappedBPETokenizer;
+pub use special::{EncodeOptions, SpecialTokenSet};
+pub use observer::{SilentObserver, TqdmObserver, TrainingObserver};
+pub use stream::StreamDecoder;
+pub use trainer::{StopReason, TrainingReport};
 pub use tokenizer::*;
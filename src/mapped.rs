@@ -0,0 +1,197 @@
+use memmap2::Mmap;
+use std::cmp::Ordering;
+use std::fs::File;
+use std::ops::Range;
+use std::path::Path;
+
+use crate::binary::{parse, u32_at};
+use crate::config::TokenizerConfig;
+use crate::error::{Result, TokenizerError};
+use crate::fingerprint::{to_hex, Digest256};
+use crate::special::{EncodeOptions, SpecialTokens};
+use crate::tokenizer::{
+    merge_chunk, par_encode, pre_tokenize, Token, Tokenizer, BYTE_VOCAB_SIZE,
+};
+use crate::util::Segment;
+
+/// Read-only tokenizer over a memory-mapped binary `.tkn` file.
+///
+/// Merges are binary searched in the mapped tables instead of being loaded
+/// into hash maps, so opening is cheap and processes serving the same file
+/// share one copy of it in the page cache. Encodes and decodes exactly like
+/// the `BPETokenizer` the file was saved from.
+pub struct MappedBPETokenizer {
+    pub config: TokenizerConfig,
+    mmap: Mmap,
+    merges: Range<usize>,
+    merge_order: Range<usize>,
+    ids: Range<usize>,
+    id_order: Range<usize>,
+    specials: SpecialTokens,
+    digest: Digest256,
+}
+
+impl MappedBPETokenizer {
+    /// maps a file written by `save_binary`, or `save_file` to a `.tkn` path.
+    ///
+    /// the file must not be modified while it is mapped.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let file = File::open(path)?;
+        // SAFETY: the mapping is only read, and the tables are checked by
+        // `parse` before any lookup. truncating the file underneath us is the
+        // caller's responsibility, as documented above.
+        let mmap = unsafe { Mmap::map(&file)? };
+
+        let (config, tables) = parse(&mmap)?;
+        let Some(merge_order) = tables.merge_order else {
+            return Err(TokenizerError::Unsupported(
+                "binary format version 1 can't be mapped, save the tokenizer again".to_string(),
+            ));
+        };
+
+        let specials = SpecialTokens::new(config.special_tokens_map.as_ref());
+
+        Ok(Self {
+            config,
+            merges: tables.merges,
+            merge_order,
+            ids: tables.ids.clone(),
+            id_order: tables.id_order.unwrap_or(tables.ids),
+            specials,
+            digest: tables.fingerprint,
+            mmap,
+        })
+    }
+
+    /// merged and special tokens, like `BPETokenizer::len`
+    pub fn len(&self) -> usize {
+        self.merges.len() / 12 + self.config.special_tokens_map.as_ref().map_or(0, |m| m.len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// same as `BPETokenizer::fingerprint` for the tokenizer the file was saved from
+    pub fn fingerprint(&self) -> String {
+        to_hex(&self.digest)
+    }
+
+    // (left, right, id) of the `i`th merge in pair order
+    fn merge(&self, i: usize) -> (Token, Token, Token) {
+        let table = &self.mmap[self.merges.clone()];
+        (u32_at(table, 3 * i), u32_at(table, 3 * i + 1), u32_at(table, 3 * i + 2))
+    }
+
+    fn rank(&self, pair: (Token, Token)) -> Option<Token> {
+        let (mut lo, mut hi) = (0, self.merges.len() / 12);
+        while lo < hi {
+            let mid = (lo + hi) / 2;
+            let (l, r, id) = self.merge(mid);
+            match (l, r).cmp(&pair) {
+                Ordering::Less => lo = mid + 1,
+                Ordering::Greater => hi = mid,
+                Ordering::Equal => return Some(id),
+            }
+        }
+        None
+    }
+
+    // the pair an internal id was merged from
+    fn unmerge(&self, id: Token) -> Option<(Token, Token)> {
+        let order = &self.mmap[self.merge_order.clone()];
+        let n = order.len() / 4;
+        let i = partition(n, |i| self.merge(u32_at(order, i) as usize).2 < id);
+        if i == n {
+            return None;
+        }
+        let (l, r, merged) = self.merge(u32_at(order, i) as usize);
+        (merged == id).then_some((l, r))
+    }
+
+    fn external(&self, id: Token) -> Token {
+        match self.ids.is_empty() {
+            true => id,
+            false => u32_at(&self.mmap[self.ids.clone()], id as usize),
+        }
+    }
+
+    fn internal(&self, id: Token) -> Option<Token> {
+        if self.ids.is_empty() {
+            return Some(id);
+        }
+        let (ids, order) = (&self.mmap[self.ids.clone()], &self.mmap[self.id_order.clone()]);
+        let n = order.len() / 4;
+        let i = partition(n, |i| u32_at(ids, u32_at(order, i) as usize) < id);
+        let internal = (i < n).then(|| u32_at(order, i))?;
+        (u32_at(ids, internal as usize) == id).then_some(internal)
+    }
+
+    fn encode_segments(&self, segments: &[Segment<Token>]) -> Vec<Token> {
+        let mut tokens = Vec::new();
+        for segment in segments {
+            match segment {
+                Segment::Text(piece) => {
+                    let piece: Vec<Token> = piece.bytes().map(|b| b as Token).collect();
+                    tokens.extend(merge_chunk(&piece, |pair| self.rank(pair)));
+                }
+                Segment::Special(id) => tokens.push(*id),
+            }
+        }
+        tokens
+    }
+
+    // appends the bytes behind an internal id, false if it is unknown
+    fn push_bytes(&self, id: Token, out: &mut Vec<u8>) -> bool {
+        let mut stack = vec![id];
+        while let Some(id) = stack.pop() {
+            if (id as usize) < BYTE_VOCAB_SIZE {
+                out.push(id as u8);
+            } else if let Some((l, r)) = self.unmerge(id) {
+                stack.extend([r, l]);
+            } else if let Some(token) = self.specials.text(id) {
+                out.extend_from_slice(token.as_bytes());
+            } else {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+// first index in 0..n for which `before` is false, `before` being true then false
+fn partition(n: usize, before: impl Fn(usize) -> bool) -> usize {
+    let (mut lo, mut hi) = (0, n);
+    while lo < hi {
+        let mid = (lo + hi) / 2;
+        match before(mid) {
+            true => lo = mid + 1,
+            false => hi = mid,
+        }
+    }
+    lo
+}
+
+impl Tokenizer for MappedBPETokenizer {
+    fn try_encode_with(&self, text: &str, options: &EncodeOptions) -> Result<Vec<Token>> {
+        let segments = pre_tokenize(&self.config, &self.specials, text, options)?;
+        let tokens = par_encode(&segments, |batch| self.encode_segments(batch));
+        Ok(tokens.into_iter().map(|id| self.external(id)).collect())
+    }
+
+    fn decode_bytes(&self, input_ids: &[Token]) -> Result<Vec<u8>> {
+        let mut out = Vec::new();
+        for &id in input_ids {
+            let internal = self.internal(id).ok_or(TokenizerError::UnknownToken(id))?;
+            if !self.push_bytes(internal, &mut out) {
+                return Err(TokenizerError::UnknownToken(id));
+            }
+        }
+        Ok(out)
+    }
+
+    fn id_to_bytes(&self, id: Token) -> Option<Vec<u8>> {
+        let mut out = Vec::new();
+        self.push_bytes(self.internal(id)?, &mut out).then_some(out)
+    }
+}
@@ -0,0 +1,58 @@
+use std::ops::ControlFlow;
+
+use tqdm::Pbar;
+
+use crate::tokenizer::Token;
+use crate::trainer::TrainingReport;
+
+/// Hooks into a training run. Every method does nothing by default, so
+/// implementations only override the events they care about.
+///
+/// Returning `ControlFlow::Break` from any of them stops training with
+/// `TokenizerError::Interrupted` and leaves the tokenizer as it was.
+pub trait TrainingObserver {
+    /// a pair seen `count` times was merged into `token`
+    fn on_merge(&mut self, _pair: (Token, Token), _token: Token, _count: u64) -> ControlFlow<()> {
+        ControlFlow::Continue(())
+    }
+
+    /// `done` out of at most `total` merges, called once with `done == 0` before the first merge
+    fn on_progress(&mut self, _done: usize, _total: usize) -> ControlFlow<()> {
+        ControlFlow::Continue(())
+    }
+
+    /// training ended, see `report.stop_reason` for why
+    fn on_finish(&mut self, _report: &TrainingReport) -> ControlFlow<()> {
+        ControlFlow::Continue(())
+    }
+}
+
+/// ignores every event
+pub struct SilentObserver;
+
+impl TrainingObserver for SilentObserver {}
+
+/// draws a progress bar on stderr, used when training without an observer
+#[derive(Default)]
+pub struct TqdmObserver {
+    bar: Option<Pbar>,
+}
+
+impl TrainingObserver for TqdmObserver {
+    fn on_progress(&mut self, done: usize, total: usize) -> ControlFlow<()> {
+        match &mut self.bar {
+            Some(bar) => {
+                let _ = bar.update(1);
+            }
+            None if done == 0 => self.bar = Some(tqdm::pbar(Some(total))),
+            None => {}
+        }
+        ControlFlow::Continue(())
+    }
+
+    fn on_finish(&mut self, _report: &TrainingReport) -> ControlFlow<()> {
+        // dropping the bar closes it
+        self.bar = None;
+        ControlFlow::Continue(())
+    }
+}
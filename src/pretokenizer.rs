@@ -0,0 +1,127 @@
+use fancy_regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+use crate::error::Result;
+
+// https://github.com/openai/tiktoken/blob/main/tiktoken_ext/openai_public.py
+pub const GPT2_PATTERN: &str =
+    r"'s|'t|'re|'ve|'m|'ll|'d| ?\p{L}+| ?\p{N}+| ?[^\s\p{L}\p{N}]+|\s+(?!\S)|\s+";
+pub const CL100K_PATTERN: &str = r"(?i:'s|'t|'re|'ve|'m|'ll|'d)|[^\r\n\p{L}\p{N}]?\p{L}+|\p{N}{1,3}| ?[^\s\p{L}\p{N}]+[\r\n]*|\s*[\r\n]+|\s+(?!\S)|\s+";
+pub const O200K_PATTERN: &str = concat!(
+    r"[^\r\n\p{L}\p{N}]?[\p{Lu}\p{Lt}\p{Lm}\p{Lo}\p{M}]*[\p{Ll}\p{Lm}\p{Lo}\p{M}]+(?i:'s|'t|'re|'ve|'m|'ll|'d)?",
+    r"|[^\r\n\p{L}\p{N}]?[\p{Lu}\p{Lt}\p{Lm}\p{Lo}\p{M}]+[\p{Ll}\p{Lm}\p{Lo}\p{M}]*(?i:'s|'t|'re|'ve|'m|'ll|'d)?",
+    r"|\p{N}{1,3}| ?[^\s\p{L}\p{N}]+[\r\n/]*|\s*[\r\n]+|\s+(?!\S)|\s+",
+);
+pub const WHITESPACE_PATTERN: &str = r"\s+|\S+";
+pub const PUNCTUATION_PATTERN: &str = r"\p{P}|[^\p{P}]+";
+pub const DIGITS_PATTERN: &str = r"\p{N}|[^\p{N}]+";
+
+/// splits text into pieces which merges are never allowed to cross.
+///
+/// implementations must be lossless, i.e. concatenating the pieces gives back `text`.
+pub trait PreTokenize {
+    fn pre_tokenize<'a>(&self, text: &'a str) -> Result<Vec<&'a str>>;
+}
+
+/// keeps text as a single piece
+pub struct IdentityPreTokenizer;
+
+impl PreTokenize for IdentityPreTokenizer {
+    fn pre_tokenize<'a>(&self, text: &'a str) -> Result<Vec<&'a str>> {
+        if text.is_empty() {
+            return Ok(vec![]);
+        }
+        Ok(vec![text])
+    }
+}
+
+/// splits text on the matches of a (lazily compiled) regex
+pub struct RegexPreTokenizer {
+    regex: &'static Regex,
+}
+
+impl RegexPreTokenizer {
+    fn new(cell: &'static OnceLock<Regex>, pattern: &str) -> Self {
+        let regex = cell.get_or_init(|| Regex::new(pattern).expect("invalid pre-tokenizer pattern"));
+        Self { regex }
+    }
+}
+
+impl PreTokenize for RegexPreTokenizer {
+    fn pre_tokenize<'a>(&self, text: &'a str) -> Result<Vec<&'a str>> {
+        let pieces = self
+            .regex
+            .find_iter(text)
+            .map(|m| Ok(m?.as_str()))
+            .collect::<Result<_>>()?;
+        Ok(pieces)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Copy, Default)]
+pub enum PreTokenizer {
+    /// no splitting, merges may span the whole input so encoding runs on a single thread
+    None,
+    /// isolate runs of whitespace from runs of non-whitespace
+    Whitespace,
+    /// isolate every punctuation character
+    Punctuation,
+    /// isolate every digit
+    Digits,
+    /// gpt2 split pattern
+    #[default]
+    Gpt2,
+    /// cl100k_base split pattern
+    Cl100k,
+    /// o200k_base split pattern
+    O200k,
+}
+
+impl PreTokenizer {
+    /// strategy used by files saved before pre-tokenization existed
+    pub fn legacy() -> Self {
+        PreTokenizer::None
+    }
+
+    pub fn into_strategy(&self) -> Box<dyn PreTokenize + Send + Sync> {
+        static GPT2: OnceLock<Regex> = OnceLock::new();
+        static CL100K: OnceLock<Regex> = OnceLock::new();
+        static O200K: OnceLock<Regex> = OnceLock::new();
+        static WHITESPACE: OnceLock<Regex> = OnceLock::new();
+        static PUNCTUATION: OnceLock<Regex> = OnceLock::new();
+        static DIGITS: OnceLock<Regex> = OnceLock::new();
+
+        match &self {
+            PreTokenizer::None => Box::new(IdentityPreTokenizer),
+            PreTokenizer::Whitespace => {
+                Box::new(RegexPreTokenizer::new(&WHITESPACE, WHITESPACE_PATTERN))
+            }
+            PreTokenizer::Punctuation => {
+                Box::new(RegexPreTokenizer::new(&PUNCTUATION, PUNCTUATION_PATTERN))
+            }
+            PreTokenizer::Digits => Box::new(RegexPreTokenizer::new(&DIGITS, DIGITS_PATTERN)),
+            PreTokenizer::Gpt2 => Box::new(RegexPreTokenizer::new(&GPT2, GPT2_PATTERN)),
+            PreTokenizer::Cl100k => Box::new(RegexPreTokenizer::new(&CL100K, CL100K_PATTERN)),
+            PreTokenizer::O200k => Box::new(RegexPreTokenizer::new(&O200K, O200K_PATTERN)),
+        }
+    }
+}
+
+impl FromStr for PreTokenizer {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "none" => Ok(PreTokenizer::None),
+            "whitespace" => Ok(PreTokenizer::Whitespace),
+            "punctuation" => Ok(PreTokenizer::Punctuation),
+            "digits" => Ok(PreTokenizer::Digits),
+            "gpt2" => Ok(PreTokenizer::Gpt2),
+            "cl100k" => Ok(PreTokenizer::Cl100k),
+            "o200k" => Ok(PreTokenizer::O200k),
+            _ => Err(format!("unknown pre-tokenizer: {}", s)),
+        }
+    }
+}
@@ -2,24 +2,32 @@ use serde::{Deserialize, Serialize};
 use std::fs::{read_to_string, File};
 use std::path::Path;
 
+use crate::error::Result;
+
+/// json persistence for anything serde can read and write
+///
+/// errors raised while deserializing, such as a file from a newer version or one
+/// that doesn't match its fingerprint, come back as `TokenizerError::Serialization`.
+/// Use `BPETokenizer::from_file` or `TokenizerConfig::from_file` to get them as
+/// `TokenizerError::UnsupportedVersion` and `TokenizerError::FingerprintMismatch`.
 pub trait Pretrained: Sized {
-    fn save_pretrained<P: AsRef<Path>>(&self, path: P) -> Result<(), std::io::Error>;
-    fn from_pretrained<P: AsRef<Path>>(path: P) -> Result<Self, std::io::Error>;
+    fn save_pretrained<P: AsRef<Path>>(&self, path: P) -> Result<()>;
+    fn from_pretrained<P: AsRef<Path>>(path: P) -> Result<Self>;
 }
 
 impl<T> Pretrained for T
 where
     T: Serialize + for<'a> Deserialize<'a>,
 {
-    fn save_pretrained<P: AsRef<Path>>(&self, path: P) -> Result<(), std::io::Error> {
+    fn save_pretrained<P: AsRef<Path>>(&self, path: P) -> Result<()> {
         let file = File::create(path)?;
-        serde_json::to_writer(file, &self).expect("failed to save pretrained !");
+        serde_json::to_writer(file, &self)?;
         Ok(())
     }
 
-    fn from_pretrained<P: AsRef<Path>>(path: P) -> Result<Self, std::io::Error> {
+    fn from_pretrained<P: AsRef<Path>>(path: P) -> Result<Self> {
         let s = read_to_string(path)?;
-        let config = serde_json::from_str::<Self>(&s).expect("failed to load pretrained");
+        let config = serde_json::from_str::<Self>(&s)?;
         Ok(config)
     }
 }
@@ -0,0 +1,309 @@
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::{json, Value};
+use rustc_hash::FxHashSet;
+use serde_with::{serde_as, DisplayFromStr};
+use std::sync::RwLock;
+
+use crate::config::TokenizerConfig;
+use crate::error::{Result as TokResult, TokenizerError};
+use crate::ids::IdMap;
+use crate::preproc::Normalizer;
+use crate::pretokenizer::PreTokenizer;
+use crate::tokenizer::{BPETokenizer, FwdMap, Token, VocabMap, BYTE_VOCAB_SIZE};
+
+/// version written by `save_pretrained`
+///
+/// - 0: merged and special tokens allocated from 128
+/// - 1: merged and special tokens allocated from 256
+/// - 2: optional `ids` translating internal ids for imported vocabularies
+/// - 3: every config field written out, configs saved on their own are versioned too
+pub const FORMAT_VERSION: u32 = 3;
+
+// configs saved on their own only carry a version from 3 on, the layout
+// before that matches what version 2 tokenizers embedded
+const UNVERSIONED_CONFIG: u32 = 2;
+
+// on-disk layout of a `BPETokenizer`
+#[serde_as]
+#[derive(Serialize)]
+struct TokenizerFileRef<'a> {
+    format_version: u32,
+    // sorted by id so saving the same tokenizer always gives the same bytes
+    #[serde_as(as = "Vec<((DisplayFromStr, DisplayFromStr), DisplayFromStr)>")]
+    encoder: Vec<((Token, Token), Token)>,
+    // versioned by the file around it
+    #[serde(serialize_with = "unversioned")]
+    config: &'a TokenizerConfig,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ids: Option<&'a [Token]>,
+    // checked on load when present. older readers ignore it, so adding it
+    // didn't need a new version
+    fingerprint: String,
+}
+
+// the same as read back. the config stays json until it's migrated, it's
+// small next to the encoder, which is read typed in a single pass
+#[serde_as]
+#[derive(Deserialize)]
+struct TokenizerFile {
+    // files without a version predate versioning
+    #[serde(default)]
+    format_version: u32,
+    #[serde_as(as = "Vec<((DisplayFromStr, DisplayFromStr), DisplayFromStr)>")]
+    encoder: FwdMap,
+    config: Value,
+    #[serde(default)]
+    ids: Option<Vec<Token>>,
+    #[serde(default)]
+    fingerprint: Option<String>,
+}
+
+// on-disk layout of a `TokenizerConfig` saved on its own
+#[derive(Serialize)]
+struct ConfigFileRef<'a> {
+    format_version: u32,
+    #[serde(flatten, serialize_with = "unversioned")]
+    config: &'a TokenizerConfig,
+}
+
+// `TokenizerConfig::serialize` and `TokenizerConfig::deserialize` below name the
+// derived, field-only impls, inherent functions shadow the trait methods
+fn unversioned<S: Serializer>(config: &&TokenizerConfig, serializer: S) -> Result<S::Ok, S::Error> {
+    TokenizerConfig::serialize(config, serializer)
+}
+
+impl Serialize for TokenizerConfig {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        ConfigFileRef {
+            format_version: FORMAT_VERSION,
+            config: self,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for TokenizerConfig {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        config_from_value(Value::deserialize(deserializer)?).map_err(D::Error::custom)
+    }
+}
+
+/// reads a config saved on its own, of any version
+pub(crate) fn config_from_value(mut config: Value) -> TokResult<TokenizerConfig> {
+    migrate_config(&mut config)?;
+    Ok(TokenizerConfig::deserialize(config)?)
+}
+
+impl Serialize for BPETokenizer {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut encoder: Vec<_> = self.encoder.iter().map(|(&p, &id)| (p, id)).collect();
+        encoder.sort_unstable_by_key(|&(_, id)| id);
+
+        TokenizerFileRef {
+            format_version: FORMAT_VERSION,
+            encoder,
+            config: &self.config,
+            ids: self.ids.as_ref().map(|ids| ids.as_slice()),
+            fingerprint: self.fingerprint(),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for BPETokenizer {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        TokenizerFile::deserialize(deserializer)?
+            .into_tokenizer()
+            .map_err(D::Error::custom)
+    }
+}
+
+impl TokenizerFile {
+    /// upgrades to `FORMAT_VERSION` one version at a time, then checks the
+    /// result against the fingerprint it was saved with
+    fn into_tokenizer(mut self) -> TokResult<BPETokenizer> {
+        check_version(self.format_version)?;
+        // later versions only added the optional `ids` and changed the config
+        if self.format_version == 0 {
+            self.v0_to_v1();
+        }
+        migrate_config_from(&mut self.config, self.format_version);
+        let config = TokenizerConfig::deserialize(self.config)?;
+
+        check_ids(
+            self.encoder.iter().map(|(&(l, r), &id)| (l, r, id)),
+            config.special_tokens_map.iter().flatten().map(|(_, &id)| id),
+            self.ids.as_ref().map_or(0, |ids| ids.len()),
+        )?;
+
+        let tokenizer = BPETokenizer {
+            encoder: self.encoder,
+            decoder: RwLock::new(None),
+            config,
+            ids: self.ids.map(IdMap::new),
+            specials: RwLock::new(None),
+        };
+        if let Some(expected) = self.fingerprint {
+            let found = tokenizer.fingerprint();
+            if found != expected {
+                return Err(TokenizerError::FingerprintMismatch { expected, found });
+            }
+        }
+        Ok(tokenizer)
+    }
+
+    /// v0 allocated merged and special tokens from 128, overlapping the raw bytes
+    /// 128..255. Ids referring to a merged or special token are shifted above the
+    /// byte vocabulary, anything else is kept as a raw byte.
+    fn v0_to_v1(&mut self) {
+        const V0_OFFSET: Token = 128;
+        let shift = BYTE_VOCAB_SIZE as Token - V0_OFFSET;
+
+        let specials = self.config["special_tokens_map"].as_object_mut();
+        let mut tokens: FxHashSet<Token> = self.encoder.values().copied().collect();
+        for (_, t) in specials.into_iter().flatten() {
+            // anything unreadable is left for deserialization to reject
+            if let Some(id) = t.as_u64() {
+                tokens.insert(id as Token);
+                *t = json!(id + shift as u64);
+            }
+        }
+        let migrate = |t: Token| if tokens.contains(&t) { t + shift } else { t };
+
+        self.encoder = self
+            .encoder
+            .iter()
+            .map(|(&(l, r), &id)| ((migrate(l), migrate(r)), id + shift))
+            .collect();
+    }
+}
+
+/// parses a json tokenizer, reporting migration and fingerprint failures as
+/// typed errors rather than serde ones
+pub(crate) fn tokenizer_from_str(s: &str) -> TokResult<BPETokenizer> {
+    serde_json::from_str::<TokenizerFile>(s)?.into_tokenizer()
+}
+
+/// Checks the ids of a tokenizer read from a file, so encoding and decoding
+/// can neither panic nor loop. `num_ids` is the size of its id map, 0 if it
+/// has none.
+pub(crate) fn check_ids(
+    merges: impl Iterator<Item = (Token, Token, Token)>,
+    specials: impl Iterator<Item = Token>,
+    num_ids: usize,
+) -> TokResult<()> {
+    let corrupt = |msg: String| Err(TokenizerError::Corrupt(msg));
+    let outside = |id: Token| num_ids != 0 && id as usize >= num_ids;
+
+    let mut num_merges = 0;
+    for (l, r, id) in merges {
+        // merged tokens are allocated after the tokens they're made of,
+        // anything else would make decoding loop forever
+        if (id as usize) < BYTE_VOCAB_SIZE || l >= id || r >= id || outside(id) {
+            return corrupt(format!("invalid merge ({}, {}) -> {}", l, r, id));
+        }
+        num_merges += 1;
+    }
+    let mut num_specials = 0;
+    for id in specials {
+        if (id as usize) < BYTE_VOCAB_SIZE || outside(id) {
+            return corrupt(format!("invalid special token id {}", id));
+        }
+        num_specials += 1;
+    }
+
+    let expected = BYTE_VOCAB_SIZE + num_merges + num_specials;
+    if num_ids != 0 && num_ids != expected {
+        return corrupt(format!(
+            "id map covers {} ids but the tokenizer has {}",
+            num_ids, expected
+        ));
+    }
+    Ok(())
+}
+
+fn check_version(version: u32) -> TokResult<()> {
+    match version > FORMAT_VERSION {
+        true => Err(TokenizerError::UnsupportedVersion {
+            found: version,
+            supported: FORMAT_VERSION,
+        }),
+        false => Ok(()),
+    }
+}
+
+fn version_of(value: &Value, missing: u32) -> TokResult<u32> {
+    let version = match value.get("format_version") {
+        None => missing,
+        Some(v) => match v.as_u64().and_then(|v| u32::try_from(v).ok()) {
+            Some(v) => v,
+            None => return Err(TokenizerError::Serialization(Error::custom("invalid format_version"))),
+        },
+    };
+    check_version(version)?;
+    Ok(version)
+}
+
+/// upgrades a config saved on its own to `FORMAT_VERSION`
+fn migrate_config(config: &mut Value) -> TokResult<()> {
+    let version = version_of(config, UNVERSIONED_CONFIG)?;
+    migrate_config_from(config, version);
+    Ok(())
+}
+
+fn migrate_config_from(config: &mut Value, version: u32) {
+    for from in version..FORMAT_VERSION {
+        if from == 2 {
+            config_v2_to_v3(config);
+        }
+    }
+    if let Some(config) = config.as_object_mut() {
+        config.remove("format_version");
+    }
+}
+
+/// fields added to the config before version 3 were optional, write out the
+/// values older files implied so nothing is left to serde defaults
+fn config_v2_to_v3(config: &mut Value) {
+    let Some(config) = config.as_object_mut() else {
+        return;
+    };
+    let defaults = [
+        ("special_tokens_map", Value::Null),
+        ("preproc", json!(Normalizer::default())),
+        // saved before pre-tokenization existed, so trained on unsplit text
+        ("pretokenizer", json!(PreTokenizer::legacy())),
+        ("num_threads", Value::Null),
+        ("min_frequency", Value::Null),
+        ("max_token_length", Value::Null),
+    ];
+    for (field, default) in defaults {
+        config.entry(field).or_insert(default);
+    }
+}
+
+/// reads an `Option` that has to be present, even if null. serde otherwise
+/// reads a missing `Option` field as `None`
+pub(crate) fn required<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::deserialize(deserializer)
+}
+
+/// writes special tokens ordered by id rather than in hash order
+pub(crate) fn serialize_vocab_map<S: Serializer>(
+    map: &Option<VocabMap>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match map {
+        Some(map) => {
+            let mut entries: Vec<_> = map.iter().collect();
+            entries.sort_unstable_by_key(|&(token, &id)| (id, token));
+            serializer.collect_map(entries)
+        }
+        None => serializer.serialize_none(),
+    }
+}
@@ -0,0 +1,299 @@
+use aho_corasick::{AhoCorasick, MatchKind};
+use rustc_hash::{FxHashMap, FxHashSet};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::{Arc, RwLock};
+
+use crate::error::{Result, TokenizerError};
+use crate::tokenizer::{Token, VocabMap};
+use crate::util::Segment;
+
+/// special tokens picked by their text, or every one the tokenizer has
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SpecialTokenSet {
+    All,
+    Only(FxHashSet<String>),
+}
+
+impl SpecialTokenSet {
+    pub fn none() -> Self {
+        SpecialTokenSet::Only(FxHashSet::default())
+    }
+
+    pub fn only<I, S>(tokens: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        SpecialTokenSet::Only(tokens.into_iter().map(Into::into).collect())
+    }
+
+    pub fn contains(&self, token: &str) -> bool {
+        match self {
+            SpecialTokenSet::All => true,
+            SpecialTokenSet::Only(tokens) => tokens.contains(token),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        matches!(self, SpecialTokenSet::Only(tokens) if tokens.is_empty())
+    }
+}
+
+/// How `Tokenizer::try_encode_with` treats special tokens in the text, like
+/// tiktoken's `allowed_special` and `disallowed_special`.
+///
+/// the default encodes every special token as itself and never fails.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EncodeOptions {
+    /// encoded as their special token id, other special tokens are encoded as plain text
+    pub allowed_special: SpecialTokenSet,
+    /// fail with `TokenizerError::DisallowedSpecial` if any of these appear
+    /// in the text. `All` means every special token that isn't allowed.
+    pub disallowed_special: SpecialTokenSet,
+}
+
+impl Default for EncodeOptions {
+    fn default() -> Self {
+        Self {
+            allowed_special: SpecialTokenSet::All,
+            disallowed_special: SpecialTokenSet::none(),
+        }
+    }
+}
+
+// `Only` sets past this many are dropped from the cache rather than piling up
+const MAX_CACHED_SETS: usize = 64;
+
+/// Special tokens of a tokenizer, with automata finding all of them in a
+/// single pass over the text
+pub(crate) struct SpecialTokens {
+    // key of the map this was built from, to tell when it's stale
+    key: MapKey,
+    // non-empty tokens sorted by text, indexed by pattern id
+    patterns: Vec<(String, Token)>,
+    // leftmost-longest, for splitting
+    longest: AhoCorasick,
+    // reports every occurrence, for finding disallowed tokens
+    overlapping: AhoCorasick,
+    by_id: FxHashMap<Token, String>,
+    // splitters for the `Only` sets seen so far, keyed by the indices of the
+    // patterns they allow
+    only: RwLock<FxHashMap<Vec<usize>, Arc<Splitter>>>,
+}
+
+// leftmost-longest automaton over some of the patterns
+struct Splitter {
+    patterns: Vec<(String, Token)>,
+    automaton: AhoCorasick,
+}
+
+/// size of a special token map and a hash of its entries that doesn't depend
+/// on their order, cheap enough to check on every encode
+#[derive(PartialEq, Eq)]
+struct MapKey(usize, u64);
+
+impl MapKey {
+    fn new(map: Option<&VocabMap>) -> Self {
+        let entries = map.into_iter().flatten();
+        // siphash per entry, summing a weaker hash lets swapped ids cancel out
+        let hash = entries.fold(0u64, |sum, entry| {
+            let mut hasher = DefaultHasher::new();
+            entry.hash(&mut hasher);
+            sum.wrapping_add(hasher.finish())
+        });
+        MapKey(map.map_or(0, |m| m.len()), hash)
+    }
+}
+
+fn automaton(patterns: &[(String, Token)], kind: MatchKind) -> AhoCorasick {
+    AhoCorasick::builder()
+        .match_kind(kind)
+        .build(patterns.iter().map(|(token, _)| token))
+        // only fails past ~2^31 automaton states, far more than any special tokens need
+        .expect("special tokens fit in an aho-corasick automaton")
+}
+
+impl SpecialTokens {
+    pub fn new(map: Option<&VocabMap>) -> Self {
+        let mut patterns: Vec<(String, Token)> = map
+            .into_iter()
+            .flatten()
+            .filter(|(token, _)| !token.is_empty())
+            .map(|(token, &id)| (token.clone(), id))
+            .collect();
+        patterns.sort_unstable();
+
+        let by_id = map
+            .into_iter()
+            .flatten()
+            .map(|(token, &id)| (id, token.clone()))
+            .collect();
+
+        Self {
+            key: MapKey::new(map),
+            longest: automaton(&patterns, MatchKind::LeftmostLongest),
+            overlapping: automaton(&patterns, MatchKind::Standard),
+            patterns,
+            by_id,
+            only: RwLock::new(FxHashMap::default()),
+        }
+    }
+
+    /// whether this was built from `map`
+    pub fn is_for(&self, map: Option<&VocabMap>) -> bool {
+        self.key == MapKey::new(map)
+    }
+
+    /// text of the special token `id`
+    pub fn text(&self, id: Token) -> Option<&str> {
+        self.by_id.get(&id).map(String::as_str)
+    }
+
+    /// splits `text` around the allowed special tokens, preferring the longest
+    /// when several start at the same position. errors if a disallowed one
+    /// appears anywhere in the text.
+    pub fn split<'a>(&self, text: &'a str, options: &EncodeOptions) -> Result<Vec<Segment<'a, Token>>> {
+        let EncodeOptions {
+            allowed_special: allowed,
+            disallowed_special: disallowed,
+        } = options;
+
+        if !disallowed.is_empty() {
+            for m in self.overlapping.find_overlapping_iter(text) {
+                let (token, _) = &self.patterns[m.pattern()];
+                if disallowed.contains(token) && !allowed.contains(token) {
+                    return Err(TokenizerError::DisallowedSpecial(token.clone()));
+                }
+            }
+        }
+
+        match allowed {
+            SpecialTokenSet::All => Ok(split_with(&self.longest, &self.patterns, text)),
+            SpecialTokenSet::Only(tokens) => {
+                let splitter = self.splitter(tokens);
+                Ok(split_with(&splitter.automaton, &splitter.patterns, text))
+            }
+        }
+    }
+
+    // splitter for the special tokens in `tokens`, built on first use
+    fn splitter(&self, tokens: &FxHashSet<String>) -> Arc<Splitter> {
+        let allowed: Vec<usize> = (0..self.patterns.len())
+            .filter(|&i| tokens.contains(&self.patterns[i].0))
+            .collect();
+        if let Some(splitter) = self.only.read().expect("could not acquire lock").get(&allowed) {
+            return splitter.clone();
+        }
+
+        let patterns: Vec<_> = allowed.iter().map(|&i| self.patterns[i].clone()).collect();
+        let splitter = Arc::new(Splitter {
+            automaton: automaton(&patterns, MatchKind::LeftmostLongest),
+            patterns,
+        });
+        let mut only = self.only.write().expect("could not acquire lock");
+        if only.len() >= MAX_CACHED_SETS {
+            only.clear();
+        }
+        only.insert(allowed, splitter.clone());
+        splitter
+    }
+}
+
+fn split_with<'a>(
+    automaton: &AhoCorasick,
+    patterns: &[(String, Token)],
+    text: &'a str,
+) -> Vec<Segment<'a, Token>> {
+    let mut segments = Vec::new();
+    let mut start = 0;
+    for m in automaton.find_iter(text) {
+        if m.start() > start {
+            segments.push(Segment::Text(&text[start..m.start()]));
+        }
+        segments.push(Segment::Special(patterns[m.pattern()].1));
+        start = m.end();
+    }
+    if start < text.len() {
+        segments.push(Segment::Text(&text[start..]));
+    }
+    segments
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn specials() -> SpecialTokens {
+        let map = VocabMap::from_iter(vec![
+            ("<s>".to_string(), 1),
+            ("<s><s>".to_string(), 2),
+            ("<sep>".to_string(), 3),
+        ]);
+        SpecialTokens::new(Some(&map))
+    }
+
+    #[test]
+    fn split_prefers_longest() {
+        let segments = specials().split("a<s><s>b<sep><s>", &EncodeOptions::default()).unwrap();
+        assert_eq!(
+            segments,
+            vec![
+                Segment::Text("a"),
+                Segment::Special(2),
+                Segment::Text("b"),
+                Segment::Special(3),
+                Segment::Special(1),
+            ]
+        );
+    }
+
+    #[test]
+    fn split_only_allowed() {
+        let options = EncodeOptions {
+            allowed_special: SpecialTokenSet::only(["<s>"]),
+            ..Default::default()
+        };
+        let segments = specials().split("<s><s>x<sep>", &options).unwrap();
+        assert_eq!(
+            segments,
+            vec![Segment::Special(1), Segment::Special(1), Segment::Text("x<sep>")]
+        );
+    }
+
+    #[test]
+    fn only_sets_share_cached_splitters() {
+        let specials = specials();
+        let first = specials.splitter(&FxHashSet::from_iter(["<s>".to_string()]));
+        // unknown tokens don't change which patterns are allowed
+        let second = specials.splitter(&FxHashSet::from_iter(["<s>".to_string(), "<x>".to_string()]));
+        assert!(Arc::ptr_eq(&first, &second));
+
+        let other = specials.splitter(&FxHashSet::from_iter(["<sep>".to_string()]));
+        assert!(!Arc::ptr_eq(&first, &other));
+    }
+
+    #[test]
+    fn is_for_notices_changes() {
+        let map = VocabMap::from_iter(vec![("<s>".to_string(), 1), ("</s>".to_string(), 2)]);
+        let specials = SpecialTokens::new(Some(&map));
+        assert!(specials.is_for(Some(&map.clone())));
+
+        let swapped = VocabMap::from_iter(vec![("<s>".to_string(), 2), ("</s>".to_string(), 1)]);
+        assert!(!specials.is_for(Some(&swapped)));
+        assert!(!specials.is_for(None));
+    }
+
+    #[test]
+    fn split_rejects_disallowed() {
+        let options = EncodeOptions {
+            allowed_special: SpecialTokenSet::only(["<s>"]),
+            disallowed_special: SpecialTokenSet::All,
+        };
+        let specials = specials();
+        assert!(specials.split("<s> and <s>", &options).is_ok());
+        assert!(matches!(
+            specials.split("<s> and <sep>", &options),
+            Err(TokenizerError::DisallowedSpecial(token)) if token == "<sep>"
+        ));
+    }
+}
@@ -0,0 +1,85 @@
+use crate::error::{Result, TokenizerError};
+use crate::tokenizer::{Token, Tokenizer};
+
+/// holds back the bytes of utf-8 characters split across several tokens
+#[derive(Default)]
+pub(crate) struct Utf8Buffer {
+    buffer: Vec<u8>,
+}
+
+impl Utf8Buffer {
+    /// appends `bytes` and returns all the text completed so far. invalid
+    /// sequences are replaced with U+FFFD, an incomplete tail is kept.
+    pub fn push(&mut self, bytes: &[u8]) -> Option<String> {
+        self.buffer.extend_from_slice(bytes);
+
+        let mut text = String::new();
+        let mut start = 0;
+        loop {
+            match std::str::from_utf8(&self.buffer[start..]) {
+                Ok(s) => {
+                    text.push_str(s);
+                    start = self.buffer.len();
+                    break;
+                }
+                Err(e) => {
+                    let valid = e.valid_up_to();
+                    text.push_str(&String::from_utf8_lossy(&self.buffer[start..start + valid]));
+
+                    match e.error_len() {
+                        Some(len) => {
+                            text.push(char::REPLACEMENT_CHARACTER);
+                            start += valid + len;
+                        }
+                        // might still be completed by the next token
+                        None => {
+                            start += valid;
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+        self.buffer.drain(..start);
+
+        (!text.is_empty()).then_some(text)
+    }
+
+    /// empties the buffer, lossily decoding whatever is left
+    pub fn flush(&mut self) -> Option<String> {
+        let text = String::from_utf8_lossy(&self.buffer).into_owned();
+        self.buffer.clear();
+
+        (!text.is_empty()).then_some(text)
+    }
+}
+
+/// Decodes ids one at a time as they are generated, only emitting complete
+/// utf-8 text fragments
+pub struct StreamDecoder<'a, T: Tokenizer + ?Sized> {
+    tokenizer: &'a T,
+    buffer: Utf8Buffer,
+}
+
+impl<'a, T: Tokenizer + ?Sized> StreamDecoder<'a, T> {
+    pub fn new(tokenizer: &'a T) -> Self {
+        Self {
+            tokenizer,
+            buffer: Utf8Buffer::default(),
+        }
+    }
+
+    /// feeds the next id, returning any text it completes
+    pub fn step(&mut self, id: Token) -> Result<Option<String>> {
+        let bytes = self
+            .tokenizer
+            .id_to_bytes(id)
+            .ok_or(TokenizerError::UnknownToken(id))?;
+        Ok(self.buffer.push(&bytes))
+    }
+
+    /// call once the stream ends to recover a dangling partial character
+    pub fn flush(&mut self) -> Option<String> {
+        self.buffer.flush()
+    }
+}
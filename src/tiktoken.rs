@@ -0,0 +1,157 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use rustc_hash::FxHashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+use crate::config::TokenizerConfig;
+use crate::error::{Result, TokenizerError};
+use crate::ids::IdMap;
+use crate::pretokenizer::PreTokenizer;
+use crate::tokenizer::{BPETokenizer, Token, VocabMap, BYTE_VOCAB_SIZE};
+
+fn unsupported<T>(msg: impl Into<String>) -> Result<T> {
+    Err(TokenizerError::Unsupported(msg.into()))
+}
+
+/// splits `token` in two the way tiktoken would build it, i.e. by merging its
+/// bytes using only tokens ranked below it
+fn split_token<'a>(
+    ranks: &FxHashMap<&[u8], Token>,
+    token: &'a [u8],
+    rank: Token,
+) -> Option<(&'a [u8], &'a [u8])> {
+    // start of every part, plus the end of the token
+    let mut bounds: Vec<usize> = (0..=token.len()).collect();
+
+    loop {
+        let best = bounds
+            .windows(3)
+            .enumerate()
+            .filter_map(|(i, w)| {
+                let r = *ranks.get(&token[w[0]..w[2]])?;
+                (r < rank).then_some((r, i))
+            })
+            .min();
+
+        match best {
+            Some((_, i)) => {
+                bounds.remove(i + 1);
+            }
+            None => break,
+        }
+    }
+
+    match bounds[..] {
+        [start, mid, end] => Some((&token[start..mid], &token[mid..end])),
+        _ => None,
+    }
+}
+
+impl BPETokenizer {
+    /// loads a tiktoken `.tiktoken` file of base64 encoded tokens and their ranks.
+    ///
+    /// the file holds neither the split pattern nor the special tokens, so both
+    /// are passed in, e.g. `PreTokenizer::Cl100k` and `("<|endoftext|>", 100257)`.
+    /// `encode` returns tiktoken's ranks as ids.
+    pub fn from_tiktoken<P: AsRef<Path>>(
+        path: P,
+        pretokenizer: PreTokenizer,
+        special_tokens: &[(&str, Token)],
+    ) -> Result<Self> {
+        let mut ranks = Vec::new();
+        for line in BufReader::new(File::open(path)?).lines() {
+            let line = line?;
+            if line.is_empty() {
+                continue;
+            }
+
+            let Some((token, rank)) = line.split_once(' ') else {
+                return unsupported(format!("malformed rank line {:?}", line));
+            };
+            let (Ok(token), Ok(rank)) = (STANDARD.decode(token), rank.parse::<Token>()) else {
+                return unsupported(format!("malformed rank line {:?}", line));
+            };
+            ranks.push((token, rank));
+        }
+
+        Self::from_ranks(ranks, pretokenizer, special_tokens)
+    }
+
+    /// builds a tokenizer from byte sequences and their merge priority, lowest first
+    pub fn from_ranks(
+        mut ranks: Vec<(Vec<u8>, Token)>,
+        pretokenizer: PreTokenizer,
+        special_tokens: &[(&str, Token)],
+    ) -> Result<Self> {
+        ranks.sort_unstable_by_key(|&(_, rank)| rank);
+        let by_bytes: FxHashMap<&[u8], Token> =
+            ranks.iter().map(|(token, rank)| (&token[..], *rank)).collect();
+        if by_bytes.len() != ranks.len() {
+            return unsupported("a token is ranked several times");
+        }
+
+        // raw bytes keep internal ids 0..255, whatever their rank
+        let mut to_external = vec![None; BYTE_VOCAB_SIZE];
+        let mut internal: FxHashMap<&[u8], Token> = FxHashMap::default();
+        for (token, rank) in ranks.iter() {
+            if let [b] = token[..] {
+                to_external[b as usize] = Some(*rank);
+                internal.insert(token, b as Token);
+            }
+        }
+        let Some(mut to_external) = to_external.into_iter().collect::<Option<Vec<_>>>() else {
+            return unsupported("every single byte needs a rank");
+        };
+
+        // merged tokens are allocated in rank order, so merge priorities are unchanged
+        let mut tokenizer = BPETokenizer::new(TokenizerConfig::new(1, None));
+        for (token, rank) in ranks.iter().filter(|(token, _)| token.len() > 1) {
+            let Some((left, right)) = split_token(&by_bytes, token, *rank) else {
+                return unsupported(format!("token of rank {} can't be built by merges", rank));
+            };
+
+            let id = (BYTE_VOCAB_SIZE + tokenizer.encoder.len()) as Token;
+            tokenizer.encoder.insert((internal[left], internal[right]), id);
+            internal.insert(token, id);
+            to_external.push(*rank);
+        }
+
+        if !special_tokens.is_empty() {
+            let first = BYTE_VOCAB_SIZE + tokenizer.encoder.len();
+            let specials: VocabMap = special_tokens
+                .iter()
+                .enumerate()
+                .map(|(i, (token, _))| (token.to_string(), (first + i) as Token))
+                .collect();
+            to_external.extend(special_tokens.iter().map(|&(_, id)| id));
+            tokenizer.config.special_tokens_map = Some(specials);
+        }
+
+        tokenizer.config.pretokenizer = pretokenizer;
+        tokenizer.config.vocab_size = tokenizer.len();
+        tokenizer.ids = Some(IdMap::new(to_external));
+        Ok(tokenizer)
+    }
+
+    /// writes every byte and merged token with its id as a `.tiktoken` rank file,
+    /// special tokens have no place in the format and are left out
+    pub fn save_tiktoken<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        // merged ids aren't contiguous when special tokens were added before training
+        let merged = self.encoder.values().copied();
+        let mut ranks = Vec::with_capacity(BYTE_VOCAB_SIZE + self.encoder.len());
+        for id in (0..BYTE_VOCAB_SIZE as Token).chain(merged) {
+            let bytes = self._id_to_bytes(id).ok_or(TokenizerError::UnknownToken(id))?;
+            ranks.push((self._external(id), bytes));
+        }
+        ranks.sort_unstable();
+
+        let mut file = BufWriter::new(File::create(path)?);
+        for (rank, bytes) in ranks {
+            writeln!(file, "{} {}", STANDARD.encode(bytes), rank)?;
+        }
+        file.flush()?;
+        Ok(())
+    }
+}
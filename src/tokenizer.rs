@@ -1,60 +1,258 @@
 use rayon::prelude::*;
-use rustc_hash::{FxBuildHasher, FxHashMap};
-use serde::{Deserialize, Serialize};
-use serde_with::{serde_as, DisplayFromStr};
-use std::sync::RwLock;
-use std::str;
-
+use rustc_hash::FxHashMap;
+use std::borrow::Cow;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+
+use crate::checkpoint::{Checkpoint, CheckpointRef};
 use crate::config::TokenizerConfig;
-use crate::util::{inject_special_tokens, ngram_replace, replace_special_tokens};
+use crate::error::{Result, TokenizerError};
+use crate::ids::IdMap;
+use crate::observer::{TqdmObserver, TrainingObserver};
+use crate::pretrained::Pretrained;
+use crate::special::{EncodeOptions, SpecialTokens};
+use crate::trainer::{merge_counts, StopReason, ThreadPool, Trainer, TrainingReport, WordCounts};
+use crate::util::Segment;
 
 
 pub type Token = u32; // 2^32 - 1 max new tokens
 
+/// raw bytes take ids 0..255, merged and special tokens are allocated above
+pub const BYTE_VOCAB_SIZE: usize = 256;
+
+// bytes of text handed to each rayon task
+const CHUNK_SIZE: usize = 1024;
+
+// bytes of text buffered before counting when training from a stream
+const BATCH_SIZE: usize = 1 << 20;
+
 // map aliases
 pub type FwdMap = FxHashMap<(Token, Token), Token>;
 pub type BkwdMap = FxHashMap<Token, (Token, Token)>;
 pub type VocabMap = FxHashMap<String, Token>;
 
 pub trait Tokenizer {
-    fn encode(&self, text: &str) -> Vec<Token>;
-    fn decode(&self, input_ids: &[Token]) -> String;
+    /// encodes `text`, treating special tokens in it as `options` says
+    fn try_encode_with(&self, text: &str, options: &EncodeOptions) -> Result<Vec<Token>>;
+
+    /// encodes `text` with every special token in it recognized
+    fn try_encode(&self, text: &str) -> Result<Vec<Token>> {
+        self.try_encode_with(text, &EncodeOptions::default())
+    }
+
+    /// raw bytes behind `input_ids`, which need not form valid utf-8
+    fn decode_bytes(&self, input_ids: &[Token]) -> Result<Vec<u8>>;
+
+    /// bytes behind a single id, `None` if the id is unknown
+    fn id_to_bytes(&self, id: Token) -> Option<Vec<u8>>;
+
+    fn try_decode(&self, input_ids: &[Token]) -> Result<String> {
+        Ok(String::from_utf8(self.decode_bytes(input_ids)?)?)
+    }
+
+    /// decodes invalid utf-8 and unknown ids to U+FFFD instead of failing
+    fn decode_lossy(&self, input_ids: &[Token]) -> String {
+        let bytes: Vec<u8> = input_ids
+            .iter()
+            .flat_map(|&id| {
+                self.id_to_bytes(id)
+                    .unwrap_or_else(|| char::REPLACEMENT_CHARACTER.to_string().into_bytes())
+            })
+            .collect();
+        String::from_utf8_lossy(&bytes).into_owned()
+    }
+
+    /// like `try_encode` but panics on error
+    fn encode(&self, text: &str) -> Vec<Token> {
+        self.try_encode(text).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// like `try_decode` but panics on error
+    fn decode(&self, input_ids: &[Token]) -> String {
+        self.try_decode(input_ids).unwrap_or_else(|e| panic!("{}", e))
+    }
 }
 
-#[serde_as]
-#[derive(Serialize, Deserialize)]
+// (de)serialized through `crate::schema`
 pub struct BPETokenizer {
-    #[serde_as(as = "Vec<((DisplayFromStr, DisplayFromStr), DisplayFromStr)>")]
     pub encoder: FwdMap,
-    #[serde(skip)]
     pub decoder: RwLock<Option<BkwdMap>>, // thread-safe & nullable
     pub config: TokenizerConfig,
+    /// set for imported vocabularies, `encoder` and special tokens keep internal ids
+    pub ids: Option<IdMap>,
+    // matcher for `config.special_tokens_map`, rebuilt lazily when it changes
+    pub(crate) specials: RwLock<Option<Arc<SpecialTokens>>>,
+}
+
+/// splits `text` on special tokens then pre-tokenizes the remaining text
+pub(crate) fn pre_tokenize<'a>(
+    config: &TokenizerConfig,
+    specials: &SpecialTokens,
+    text: &'a str,
+    options: &EncodeOptions,
+) -> Result<Vec<Segment<'a, Token>>> {
+    let pretokenizer = config.pretokenizer.into_strategy();
+    let segments = specials.split(text, options)?;
+
+    let mut pieces = Vec::new();
+    for segment in segments {
+        match segment {
+            Segment::Text(t) => {
+                pieces.extend(pretokenizer.pre_tokenize(t)?.into_iter().map(Segment::Text))
+            }
+            special => pieces.push(special),
+        }
+    }
+    Ok(pieces)
+}
+
+/// applies merges lowest rank (i.e. lowest token id) first, leftmost on ties.
+///
+/// tokens form a linked list and candidate pairs sit in a min-heap; stale heap
+/// entries are skipped on pop instead of being removed, so the whole chunk is
+/// encoded in O(n log n). `rank` looks up the token a pair merges into.
+pub(crate) fn merge_chunk(
+    chunk: &[Token],
+    rank: impl Fn((Token, Token)) -> Option<Token>,
+) -> Vec<Token> {
+    const NONE: usize = usize::MAX;
+
+    let mut tokens = chunk.to_vec();
+    let n = tokens.len();
+    let mut prev: Vec<usize> = (0..n).map(|i| i.wrapping_sub(1)).collect();
+    let mut next: Vec<usize> = (1..=n).map(|i| if i < n { i } else { NONE }).collect();
+    let mut alive = vec![true; n];
+
+    let mut heap = BinaryHeap::new();
+    for (i, pair) in tokens.windows(2).enumerate() {
+        if let Some(r) = rank((pair[0], pair[1])) {
+            heap.push(Reverse((r, i)));
+        }
+    }
+
+    while let Some(Reverse((r, i))) = heap.pop() {
+        let j = next[i];
+        // stale: either side was merged away since this entry was pushed
+        if !alive[i] || j == NONE || rank((tokens[i], tokens[j])) != Some(r) {
+            continue;
+        }
+
+        tokens[i] = r;
+        alive[j] = false;
+        next[i] = next[j];
+        if next[i] != NONE {
+            prev[next[i]] = i;
+        }
+
+        if prev[i] != NONE {
+            if let Some(r) = rank((tokens[prev[i]], tokens[i])) {
+                heap.push(Reverse((r, prev[i])));
+            }
+        }
+        if next[i] != NONE {
+            if let Some(r) = rank((tokens[i], tokens[next[i]])) {
+                heap.push(Reverse((r, i)));
+            }
+        }
+    }
+
+    tokens
+        .into_iter()
+        .zip(alive)
+        .filter_map(|(t, alive)| alive.then_some(t))
+        .collect()
+}
+
+/// encodes pre-tokens in parallel with `encode`, which takes a batch of them
+pub(crate) fn par_encode(
+    segments: &[Segment<Token>],
+    encode: impl Fn(&[Segment<Token>]) -> Vec<Token> + Sync,
+) -> Vec<Token> {
+    // batch consecutive pre-tokens into ~CHUNK_SIZE bytes of work, only
+    // ever splitting between them so the output matches encoding in order
+    let mut batches = Vec::new();
+    let (mut start, mut size) = (0, 0);
+    for (i, segment) in segments.iter().enumerate() {
+        size += segment.len();
+        if size >= CHUNK_SIZE {
+            batches.push(&segments[start..=i]);
+            (start, size) = (i + 1, 0);
+        }
+    }
+    if start < segments.len() {
+        batches.push(&segments[start..]);
+    }
+
+    batches
+        .par_iter()
+        .flat_map_iter(|batch| encode(batch))
+        .collect()
+}
+
+/// lines of utf-8 text files with their trailing newline, stopping after the first error
+fn read_lines<P: AsRef<Path>>(paths: &[P]) -> impl Iterator<Item = Result<String>> + '_ {
+    paths.iter().flat_map(|path| {
+        let mut reader = Some(File::open(path).map(BufReader::new));
+
+        std::iter::from_fn(move || match reader.take()? {
+            Ok(mut r) => {
+                let mut line = String::new();
+                match r.read_line(&mut line) {
+                    Ok(0) => None,
+                    Ok(_) => {
+                        reader = Some(Ok(r));
+                        Some(Ok(line))
+                    }
+                    Err(e) => Some(Err(e.into())),
+                }
+            }
+            Err(e) => Some(Err(e.into())),
+        })
+    })
+}
+
+/// checkpoints are saved every `interval` merges, which has to be positive
+pub(crate) fn check_interval(interval: usize) -> Result<()> {
+    match interval {
+        0 => Err(TokenizerError::InvalidArgument(
+            "checkpoint interval must be positive".to_string(),
+        )),
+        _ => Ok(()),
+    }
 }
 
 impl Tokenizer for BPETokenizer {
-    fn encode(&self, text: &str) -> Vec<Token> {
-        // parallel
-        text.as_bytes()
-            .par_chunks(1024)
-            .flat_map_iter(|c| {
-                let chunk_as_tokens: Vec<Token> = c.iter().map(|&i| i as Token).collect();
-                self._encode_chunk(&chunk_as_tokens)
-            })
-            .collect()
+    fn try_encode_with(&self, text: &str, options: &EncodeOptions) -> Result<Vec<Token>> {
+        let segments = self._pre_tokenize(text, options)?;
+        let tokens = par_encode(&segments, |batch| self._encode_segments(batch));
+        Ok(self._to_external(tokens))
     }
 
-    fn decode(&self, input_ids: &[Token]) -> String {
+    fn decode_bytes(&self, input_ids: &[Token]) -> Result<Vec<u8>> {
+        let input_ids = self._to_internal(input_ids)?;
+
         // first pass
         let raw_tokens: Vec<Token> = input_ids
-            .par_chunks(1024)
+            .par_chunks(CHUNK_SIZE)
             .flat_map_iter(|t| self._decode_chunk(t))
             .collect();
 
-        let bytes: Vec<u8> = raw_tokens.iter().map(|&t| t as u8).collect();
+        // anything left above the byte range was never allocated
+        raw_tokens
+            .iter()
+            .map(|&t| u8::try_from(t).map_err(|_| TokenizerError::UnknownToken(t)))
+            .collect()
+    }
 
-        let s = str::from_utf8(&bytes)
-            .unwrap_or_else(|_| panic!("failed to decode into valid utf-8: {:?}", bytes));
-        s.into()
+    fn id_to_bytes(&self, id: Token) -> Option<Vec<u8>> {
+        match &self.ids {
+            Some(ids) => self._id_to_bytes(ids.internal(id)?),
+            None => self._id_to_bytes(id),
+        }
     }
 }
 
@@ -64,6 +262,8 @@ impl BPETokenizer {
             encoder: FwdMap::default(),
             decoder: RwLock::new(None),
             config,
+            ids: None,
+            specials: RwLock::new(None),
         }
     }
 
@@ -74,6 +274,10 @@ impl BPETokenizer {
         }
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
     fn _sync_decoder(&self) {
         let mut inner = self.decoder.write().unwrap();
         inner.replace(
@@ -84,8 +288,49 @@ impl BPETokenizer {
         );
     }
 
+    /// external id of an internal one
+    pub(crate) fn _external(&self, id: Token) -> Token {
+        match &self.ids {
+            Some(ids) => ids.external(id).expect("every internal id is mapped"),
+            None => id,
+        }
+    }
+
+    fn _to_external(&self, mut tokens: Vec<Token>) -> Vec<Token> {
+        if self.ids.is_some() {
+            tokens.iter_mut().for_each(|t| *t = self._external(*t));
+        }
+        tokens
+    }
+
+    fn _to_internal<'a>(&self, tokens: &'a [Token]) -> Result<Cow<'a, [Token]>> {
+        match &self.ids {
+            Some(ids) => tokens
+                .iter()
+                .map(|&t| ids.internal(t).ok_or(TokenizerError::UnknownToken(t)))
+                .collect::<Result<Vec<_>>>()
+                .map(Cow::Owned),
+            None => Ok(Cow::Borrowed(tokens)),
+        }
+    }
+
+    // gives ids allocated since the last call an external id
+    fn _sync_ids(&mut self) {
+        let len = self.len() + BYTE_VOCAB_SIZE;
+        if let Some(ids) = self.ids.as_mut() {
+            ids.extend_to(len);
+        }
+    }
+
+    pub(crate) fn _id_to_bytes(&self, id: Token) -> Option<Vec<u8>> {
+        self._decode_chunk(&[id])
+            .into_iter()
+            .map(|t| u8::try_from(t).ok())
+            .collect()
+    }
+
     pub fn add_special_tokens<S: Into<String>>(&mut self, tokens: Vec<S>) {
-        let token_id = self.len() + 128;
+        let token_id = self.len() + BYTE_VOCAB_SIZE;
         let token_map: VocabMap = tokens
             .into_iter()
             .enumerate()
@@ -100,6 +345,7 @@ impl BPETokenizer {
                     m.extend(token_map);
                     Some(m)
                 });
+        self._sync_ids();
     }
 
     pub fn preprocess(&self, text: &mut String) {
@@ -107,75 +353,67 @@ impl BPETokenizer {
         preproc.normalize(text);
     }
 
-    fn _encode_chunk(&self, chunk: &[Token]) -> Vec<Token> {
-        let mut tokens = chunk.to_vec().clone();
-
-        if let Some(map) = self.config.special_tokens_map.as_ref() {
-            replace_special_tokens::<Token, FxBuildHasher>(&mut tokens, map);
-        }
-
-        loop {
-            let mut merges = Vec::new();
-
-            for i in 0..tokens.len() - 1 {
-                if let Some(&new_token) = self.encoder.get(&(tokens[i], tokens[i + 1])) {
-                    merges.push((i, new_token));
-                }
+    // matcher for the current special tokens, rebuilt if they were changed
+    fn _special_tokens(&self) -> Arc<SpecialTokens> {
+        let map = self.config.special_tokens_map.as_ref();
+        if let Some(specials) = self.specials.read().expect("could not acquire lock").as_ref() {
+            if specials.is_for(map) {
+                return specials.clone();
             }
-            // early stopping: no more token pairs in merge rules
-            if merges.is_empty() {
-                break;
-            }
-
-            // apply merges and swap in tokens in reverse
-            let mut i = merges.len() - 1;
-
-            while i > 0 {
-                let x = &mut merges[i - 1..=i];
-                let mut l = x[0];
-                let r = x[1];
+        }
+        let specials = Arc::new(SpecialTokens::new(map));
+        self.specials.write().expect("could not acquire lock").replace(specials.clone());
+        specials
+    }
 
-                if r.0 - l.0 > 1 && r.1 != Token::MAX {
-                    tokens[r.0] = r.1;
-                    tokens.remove(r.0 + 1);
-                } else if r.1 < l.1 {
-                    tokens[r.0] = r.1;
-                    tokens.remove(r.0 + 1);
+    fn _pre_tokenize<'a>(&self, text: &'a str, options: &EncodeOptions) -> Result<Vec<Segment<'a, Token>>> {
+        pre_tokenize(&self.config, &self._special_tokens(), text, options)
+    }
 
-                    l.1 = Token::MAX;
-                    i -= 1;
-                }
+    /// single-threaded reference for `Tokenizer::encode`, useful for short inputs
+    pub fn encode_sequential(&self, text: &str) -> Result<Vec<Token>> {
+        let tokens = self._encode_segments(&self._pre_tokenize(text, &EncodeOptions::default())?);
+        Ok(self._to_external(tokens))
+    }
 
-                //avoid overflow on usize 0-1
-                if i == 0 {
-                    break;
+    fn _encode_segments(&self, segments: &[Segment<Token>]) -> Vec<Token> {
+        segments
+            .iter()
+            .flat_map(|segment| match segment {
+                Segment::Text(piece) => {
+                    let piece_as_tokens: Vec<Token> = piece.bytes().map(|i| i as Token).collect();
+                    self._encode_chunk(&piece_as_tokens)
                 }
-                i -= 1;
-            }
+                Segment::Special(t) => vec![*t],
+            })
+            .collect()
+    }
 
-            // edge case
-            if merges.len() == 1 || merges[0].1 < merges[1].1 {
-                tokens[merges[0].0] = merges[0].1;
-                tokens.remove(merges[0].0 + 1);
-            }
-        }
-        tokens
+    fn _encode_chunk(&self, chunk: &[Token]) -> Vec<Token> {
+        merge_chunk(chunk, |pair| self.encoder.get(&pair).copied())
     }
 
     fn _decode_chunk(&self, tokens: &[Token]) -> Vec<Token> {
         let mut tokens: Vec<Token> = Vec::from(tokens);
 
-        // lazy init
-        self._sync_decoder();
+        // lazy init, and rebuilt if `encoder` was changed directly since
+        let stale = self
+            .decoder
+            .read()
+            .expect("could not acquire lock")
+            .as_ref()
+            .is_none_or(|decoder| decoder.len() != self.encoder.len());
+        if stale {
+            self._sync_decoder();
+        }
         let lock = self.decoder.read().expect("could not acquire lock");
 
         let decoder = lock.as_ref().unwrap();
 
         loop {
             let mut demerges = Vec::new();
-            for i in 0..tokens.len() {
-                let rank = tokens[i];
-                if let Some(&tup) = decoder.get(&rank) {
+            for (i, rank) in tokens.iter().enumerate() {
+                if let Some(&tup) = decoder.get(rank) {
                     demerges.push((i, tup));
                 }
             }
@@ -191,47 +429,385 @@ impl BPETokenizer {
             }
         }
 
-        // special tokens
-        if let Some(map) = self.config.special_tokens_map.as_ref() {
-            inject_special_tokens::<Token, FxBuildHasher>(&mut tokens, map);
+        // special tokens, as the utf-8 bytes of their text
+        let specials = self._special_tokens();
+        tokens
+            .into_iter()
+            .flat_map(|t| match specials.text(t) {
+                Some(text) => text.bytes().map(Token::from).collect(),
+                None => vec![t],
+            })
+            .collect()
+    }
+
+    /// like `try_train` but panics on error.
+    ///
+    /// training is deterministic: when several pairs are equally frequent the
+    /// one whose (left, right) byte sequences sort first is merged.
+    pub fn train(&mut self, text: &str) -> TrainingReport {
+        self.try_train(text).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// adds the frequency of every pre-token in `texts` to `counts`, special tokens excluded
+    fn _count_words<S: AsRef<str> + Sync>(&self, texts: &[S], counts: &mut WordCounts) -> Result<()> {
+        let batch = texts
+            .par_iter()
+            .map(|text| -> Result<WordCounts> {
+                let segments = self._pre_tokenize(text.as_ref(), &EncodeOptions::default())?;
+                Ok(segments
+                    .par_chunks(CHUNK_SIZE)
+                    .fold(WordCounts::default, |mut counts, chunk| {
+                        for segment in chunk {
+                            if let Segment::Text(piece) = segment {
+                                match counts.get_mut(*piece) {
+                                    Some(count) => *count += 1,
+                                    None => {
+                                        counts.insert(piece.to_string(), 1);
+                                    }
+                                }
+                            }
+                        }
+                        counts
+                    })
+                    .reduce(WordCounts::default, merge_counts))
+            })
+            .try_reduce(WordCounts::default, |a, b| Ok(merge_counts(a, b)))?;
+
+        for (word, count) in batch {
+            *counts.entry(word).or_default() += count;
+        }
+        Ok(())
+    }
+
+    /// counts pre-tokens in batches of ~BATCH_SIZE bytes of text
+    pub(crate) fn _count_from_iterator<I, S>(&self, texts: I) -> Result<WordCounts>
+    where
+        I: IntoIterator<Item = Result<S>>,
+        S: AsRef<str> + Send + Sync,
+    {
+        let pool = ThreadPool::new(self.config.num_threads)?;
+        let mut counts = WordCounts::default();
+
+        let mut batch = Vec::new();
+        let mut size = 0;
+        for text in texts {
+            let text = text?;
+            size += text.as_ref().len();
+            batch.push(text);
+
+            if size >= BATCH_SIZE {
+                pool.install(|| self._count_words(&batch, &mut counts))?;
+                batch.clear();
+                size = 0;
+            }
         }
+        pool.install(|| self._count_words(&batch, &mut counts))?;
 
-        tokens
+        Ok(counts)
+    }
+
+    /// learns merges until `vocab_size` is reached or no pair passes the
+    /// config's `min_frequency` and `max_token_length` limits
+    pub fn try_train(&mut self, text: &str) -> Result<TrainingReport> {
+        self.try_train_with_observer(text, &mut TqdmObserver::default())
     }
 
-    pub fn train(&mut self, text: &str) -> Vec<Token> {
-        let mut pieces: Vec<Token>;
+    /// like `try_train`, reporting progress to `observer` instead of a progress bar
+    pub fn try_train_with_observer(
+        &mut self,
+        text: &str,
+        observer: &mut dyn TrainingObserver,
+    ) -> Result<TrainingReport> {
+        let counts = self._count_from_iterator([Ok(text)])?;
+        self._train_on_counts(counts, observer, None)
+    }
 
-        if !self.encoder.is_empty() {
-            println!("pretrained tokenizer detected!");
-            pieces = self.encode(text);
-        } else {
-            let text = text.as_bytes();
-            pieces = text.iter().map(|&i| i as Token).collect();
+    /// learns `extra_tokens` more merges from `corpus` on top of the existing
+    /// ones, e.g. to adapt a pretrained tokenizer to a new domain. ids of
+    /// existing tokens never change and `vocab_size` grows by the merges
+    /// actually learned, the report lists them and their ids.
+    pub fn extend(&mut self, corpus: &str, extra_tokens: usize) -> Result<TrainingReport> {
+        self.extend_with_observer(corpus, extra_tokens, &mut TqdmObserver::default())
+    }
+
+    pub fn extend_with_observer(
+        &mut self,
+        corpus: &str,
+        extra_tokens: usize,
+        observer: &mut dyn TrainingObserver,
+    ) -> Result<TrainingReport> {
+        let vocab_size = self.config.vocab_size;
+        self.config.vocab_size = self.len() + extra_tokens;
+
+        let report = self.try_train_with_observer(corpus, observer);
+        // training may stop short of `extra_tokens`, and failed runs are rolled back
+        self.config.vocab_size = match report {
+            Ok(_) => self.len(),
+            Err(_) => vocab_size,
+        };
+        report
+    }
+
+    /// like `try_train_with_observer`, saving a `Checkpoint` to `path` every
+    /// `interval` merges. an interrupted run continues with `resume_training`.
+    /// a checkpoint that fails to save stops training with the tokenizer as it was.
+    pub fn try_train_with_checkpoints<P: AsRef<Path>>(
+        &mut self,
+        text: &str,
+        path: P,
+        interval: usize,
+        observer: &mut dyn TrainingObserver,
+    ) -> Result<TrainingReport> {
+        check_interval(interval)?;
+        let counts = self._count_from_iterator([Ok(text)])?;
+        self._train_on_counts(counts, observer, Some((path.as_ref(), interval)))
+    }
+
+    /// continues training from a checkpoint on the corpus it was made from up to
+    /// `vocab_size`, learning the same merges as an uninterrupted run would have
+    pub fn resume_training<P: AsRef<Path>>(checkpoint: P, corpus: &str) -> Result<(Self, TrainingReport)> {
+        Self::resume_training_with_observer(checkpoint, corpus, &mut TqdmObserver::default())
+    }
+
+    pub fn resume_training_with_observer<P: AsRef<Path>>(
+        checkpoint: P,
+        corpus: &str,
+        observer: &mut dyn TrainingObserver,
+    ) -> Result<(Self, TrainingReport)> {
+        Self::_resume(checkpoint.as_ref(), observer, |tok| {
+            tok._count_from_iterator([Ok(corpus)])
+        })
+    }
+
+    /// `resume_training` for a run started by `train_from_iterator_with_checkpoints`
+    pub fn resume_training_from_iterator<P, I, S>(
+        checkpoint: P,
+        texts: I,
+        observer: &mut dyn TrainingObserver,
+    ) -> Result<(Self, TrainingReport)>
+    where
+        P: AsRef<Path>,
+        I: IntoIterator<Item = S>,
+        S: AsRef<str> + Send + Sync,
+    {
+        Self::_resume(checkpoint.as_ref(), observer, |tok| {
+            tok._count_from_iterator(texts.into_iter().map(Ok))
+        })
+    }
+
+    /// `resume_training` for a run started by `train_from_files_with_checkpoints`
+    pub fn resume_training_from_files<P: AsRef<Path>, Q: AsRef<Path>>(
+        checkpoint: P,
+        paths: &[Q],
+        observer: &mut dyn TrainingObserver,
+    ) -> Result<(Self, TrainingReport)> {
+        Self::_resume(checkpoint.as_ref(), observer, |tok| {
+            tok._count_from_iterator(read_lines(paths))
+        })
+    }
+
+    /// `count` recounts the corpus with the checkpoint's tokenizer, it has to
+    /// match the counts saved with it
+    fn _resume(
+        path: &Path,
+        observer: &mut dyn TrainingObserver,
+        count: impl FnOnce(&Self) -> Result<WordCounts>,
+    ) -> Result<(Self, TrainingReport)> {
+        let Checkpoint {
+            mut tokenizer,
+            interval,
+            counts,
+        } = Checkpoint::from_pretrained(path)?;
+        check_interval(interval)?;
+
+        if count(&tokenizer)? != counts {
+            return Err(TokenizerError::CheckpointMismatch);
         }
 
-        match self.config.vocab_size.checked_sub(self.len()) {
-            Some(size) => {
-                for _ in tqdm::tqdm(0..size) {
-                    let mut counts = FwdMap::default();
-                    for i in 0..pieces.len() - 1 {
-                        *counts.entry((pieces[i], pieces[i + 1])).or_insert(0) += 1;
-                    }
+        let report = tokenizer._train_on_counts(counts, observer, Some((path, interval)))?;
+        Ok((tokenizer, report))
+    }
+
+    /// trains on a stream of texts, only holding the counts of distinct pre-tokens in memory
+    pub fn train_from_iterator<I, S>(&mut self, texts: I) -> Result<TrainingReport>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str> + Send + Sync,
+    {
+        self.train_from_iterator_with_observer(texts, &mut TqdmObserver::default())
+    }
+
+    pub fn train_from_iterator_with_observer<I, S>(
+        &mut self,
+        texts: I,
+        observer: &mut dyn TrainingObserver,
+    ) -> Result<TrainingReport>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str> + Send + Sync,
+    {
+        let counts = self._count_from_iterator(texts.into_iter().map(Ok))?;
+        self._train_on_counts(counts, observer, None)
+    }
+
+    /// like `train_from_iterator_with_observer`, saving a `Checkpoint` to `path`
+    /// every `interval` merges. continue with `resume_training_from_iterator`.
+    pub fn train_from_iterator_with_checkpoints<P, I, S>(
+        &mut self,
+        texts: I,
+        path: P,
+        interval: usize,
+        observer: &mut dyn TrainingObserver,
+    ) -> Result<TrainingReport>
+    where
+        P: AsRef<Path>,
+        I: IntoIterator<Item = S>,
+        S: AsRef<str> + Send + Sync,
+    {
+        check_interval(interval)?;
+        let counts = self._count_from_iterator(texts.into_iter().map(Ok))?;
+        self._train_on_counts(counts, observer, Some((path.as_ref(), interval)))
+    }
+
+    /// trains on utf-8 text files, read line by line
+    pub fn train_from_files<P: AsRef<Path>>(&mut self, paths: &[P]) -> Result<TrainingReport> {
+        self.train_from_files_with_observer(paths, &mut TqdmObserver::default())
+    }
+
+    pub fn train_from_files_with_observer<P: AsRef<Path>>(
+        &mut self,
+        paths: &[P],
+        observer: &mut dyn TrainingObserver,
+    ) -> Result<TrainingReport> {
+        let counts = self._count_from_iterator(read_lines(paths))?;
+        self._train_on_counts(counts, observer, None)
+    }
+
+    /// like `train_from_files_with_observer`, saving a `Checkpoint` to `path`
+    /// every `interval` merges. continue with `resume_training_from_files`.
+    pub fn train_from_files_with_checkpoints<P: AsRef<Path>, Q: AsRef<Path>>(
+        &mut self,
+        paths: &[P],
+        path: Q,
+        interval: usize,
+        observer: &mut dyn TrainingObserver,
+    ) -> Result<TrainingReport> {
+        check_interval(interval)?;
+        let counts = self._count_from_iterator(read_lines(paths))?;
+        self._train_on_counts(counts, observer, Some((path.as_ref(), interval)))
+    }
+
+    /// `checkpoint` is where to save progress and after how many merges
+    pub(crate) fn _train_on_counts(
+        &mut self,
+        counts: WordCounts,
+        observer: &mut dyn TrainingObserver,
+        checkpoint: Option<(&Path, usize)>,
+    ) -> Result<TrainingReport> {
+        let pool = ThreadPool::new(self.config.num_threads)?;
+
+        // pick up from existing merges, if any
+        let words = counts
+            .iter()
+            .map(|(word, &count)| {
+                let bytes: Vec<Token> = word.bytes().map(|i| i as Token).collect();
+                (self._encode_chunk(&bytes), count)
+            })
+            .collect();
+
+        let mut vocab: FxHashMap<Token, Vec<u8>> =
+            (0..BYTE_VOCAB_SIZE as Token).map(|b| (b, vec![b as u8])).collect();
+        for &id in self.encoder.values() {
+            let bytes = self._id_to_bytes(id).ok_or(TokenizerError::UnknownToken(id))?;
+            vocab.insert(id, bytes);
+        }
+        // only the counting runs on the pool, observers are always called from this thread
+        let max_token_length = self.config.max_token_length;
+        let mut trainer = pool.install(|| Trainer::new(words, vocab, max_token_length));
+        let min_frequency = self.config.min_frequency.unwrap_or(1);
+
+        let mut merges = Vec::new();
+        let size = self.config.vocab_size.saturating_sub(self.len());
+        let mut stop_reason = StopReason::VocabSizeReached;
 
-                    let (&p, _) = counts.iter().max_by_key(|&(_, &c)| c).unwrap();
-                    let token_id = (self.len() + 127 + 1) as Token;
+        // restored if the observer stops training or a checkpoint fails to save
+        let before = (self.encoder.clone(), self.ids.clone());
 
-                    self.encoder.insert(p, token_id);
-                    ngram_replace(&mut pieces, &[p.0, p.1], &[token_id]);
+        if observer.on_progress(0, size).is_break() {
+            return self._rollback(before, TokenizerError::Interrupted);
+        }
+        for done in 1..=size {
+            // every pre-token already merged into a single token
+            let Some((p, count)) = trainer.best() else {
+                stop_reason = StopReason::NoPairsLeft;
+                break;
+            };
+            if count < min_frequency {
+                stop_reason = StopReason::BelowMinFrequency;
+                break;
+            }
+            let token_id = (self.len() + BYTE_VOCAB_SIZE) as Token;
+
+            self.encoder.insert(p, token_id);
+            self._sync_ids();
+            pool.install(|| trainer.merge(p, token_id));
+
+            // reported with the ids `encode` hands out
+            let merge = ((self._external(p.0), self._external(p.1)), self._external(token_id));
+            merges.push(merge);
+
+            if let Some((path, interval)) = checkpoint {
+                if done % interval == 0 {
+                    if let Err(e) = self._save_checkpoint(path, interval, &counts) {
+                        return self._rollback(before, e);
+                    }
                 }
             }
-            None => println!(
-                "requested vocab_size: {} already reached.",
-                self.config.vocab_size
-            ),
+
+            if observer.on_merge(merge.0, merge.1, count).is_break()
+                || observer.on_progress(done, size).is_break()
+            {
+                return self._rollback(before, TokenizerError::Interrupted);
+            }
+        }
+
+        self._sync_decoder();
+        let report = TrainingReport {
+            merges,
+            stop_reason,
         };
+        if observer.on_finish(&report).is_break() {
+            return self._rollback(before, TokenizerError::Interrupted);
+        }
+
+        // the final state, so resuming a finished run is a no-op
+        if let Some((path, interval)) = checkpoint {
+            if let Err(e) = self._save_checkpoint(path, interval, &counts) {
+                return self._rollback(before, e);
+            }
+        }
 
+        Ok(report)
+    }
+
+    // undoes the merges of a run that failed or the observer stopped
+    fn _rollback(
+        &mut self,
+        (encoder, ids): (FwdMap, Option<IdMap>),
+        error: TokenizerError,
+    ) -> Result<TrainingReport> {
+        self.encoder = encoder;
+        self.ids = ids;
         self._sync_decoder();
-        pieces
+        Err(error)
+    }
+
+    fn _save_checkpoint(&self, path: &Path, interval: usize, counts: &WordCounts) -> Result<()> {
+        CheckpointRef {
+            tokenizer: self,
+            interval,
+            counts,
+        }
+        .save(path)
     }
 }
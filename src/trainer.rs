@@ -0,0 +1,337 @@
+use rayon::prelude::*;
+use rustc_hash::{FxHashMap, FxHashSet};
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+use crate::error::Result;
+use crate::tokenizer::Token;
+
+pub type Pair = (Token, Token);
+pub type WordCounts = FxHashMap<String, u64>;
+type PairIndex = (FxHashMap<Pair, u64>, FxHashMap<Pair, FxHashSet<usize>>);
+
+/// why training ended
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum StopReason {
+    /// the vocabulary reached the configured `vocab_size`
+    VocabSizeReached,
+    /// every pre-token is a single token, or all remaining merges are too long
+    NoPairsLeft,
+    /// the most frequent pair occurs fewer than `min_frequency` times
+    BelowMinFrequency,
+}
+
+/// merges learned by a training run, in the order they were learned
+#[derive(Debug, Clone)]
+pub struct TrainingReport {
+    pub merges: Vec<(Pair, Token)>,
+    pub stop_reason: StopReason,
+}
+
+// heap entry, may be stale if the pair count changed after it was pushed
+#[derive(PartialEq, Eq)]
+struct PairCount {
+    pair: Pair,
+    count: u64,
+}
+
+impl Ord for PairCount {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.count
+            .cmp(&other.count)
+            .then_with(|| other.pair.cmp(&self.pair))
+    }
+}
+
+impl PartialOrd for PairCount {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Incremental BPE trainer.
+///
+/// Every distinct pre-token is stored once with its frequency. Pair counts are
+/// kept up to date across merges together with the words each pair occurs in,
+/// so a merge only touches the words containing it rather than the whole corpus.
+/// Counting and merging are sharded across rayon threads and always folded
+/// back in word order, so the learned merges don't depend on the thread count.
+///
+/// Pairs with the same count are ordered by the bytes of their left token,
+/// then the bytes of their right token, the lexicographically smallest being
+/// merged first. This only depends on the corpus, never on hash iteration order.
+pub(crate) struct Trainer {
+    vocab: FxHashMap<Token, Vec<u8>>,
+    // pairs merging into longer tokens are counted but never enter the heap
+    max_token_length: Option<usize>,
+    words: Vec<Vec<Token>>,
+    counts: Vec<u64>,
+    pair_counts: FxHashMap<Pair, u64>,
+    pair_words: FxHashMap<Pair, FxHashSet<usize>>,
+    heap: BinaryHeap<PairCount>,
+}
+
+impl Trainer {
+    /// `words` are pre-tokens already split into their initial tokens, all of
+    /// which must be in `vocab`
+    pub fn new(
+        words: Vec<(Vec<Token>, u64)>,
+        vocab: FxHashMap<Token, Vec<u8>>,
+        max_token_length: Option<usize>,
+    ) -> Self {
+        let (words, counts): (Vec<_>, Vec<_>) = words.into_iter().unzip();
+
+        // shard counting across threads, sums don't depend on how the work was split
+        let (pair_counts, pair_words) = words
+            .par_iter()
+            .enumerate()
+            .fold(
+                || (FxHashMap::default(), FxHashMap::default()),
+                |(mut pair_counts, mut pair_words): PairIndex, (i, word)| {
+                    for pair in word.windows(2) {
+                        let pair = (pair[0], pair[1]);
+                        *pair_counts.entry(pair).or_default() += counts[i];
+                        pair_words.entry(pair).or_default().insert(i);
+                    }
+                    (pair_counts, pair_words)
+                },
+            )
+            .reduce(
+                || (FxHashMap::default(), FxHashMap::default()),
+                |(mut counts_a, mut words_a), (counts_b, words_b)| {
+                    for (pair, count) in counts_b {
+                        *counts_a.entry(pair).or_default() += count;
+                    }
+                    for (pair, words) in words_b {
+                        words_a.entry(pair).or_default().extend(words);
+                    }
+                    (counts_a, words_a)
+                },
+            );
+
+        let mut trainer = Self {
+            vocab,
+            max_token_length,
+            words,
+            counts,
+            pair_counts,
+            pair_words,
+            heap: BinaryHeap::new(),
+        };
+        let pairs: Vec<_> = trainer.pair_counts.iter().map(|(&p, &c)| (p, c)).collect();
+        for (pair, count) in pairs {
+            trainer.push(pair, count);
+        }
+        trainer
+    }
+
+    /// most frequent pair and its count, `None` once no pairs are left
+    pub fn best(&mut self) -> Option<(Pair, u64)> {
+        let (_, count) = self.peek()?;
+
+        // pop every pair tied for the top count...
+        let mut tied: Vec<Pair> = Vec::new();
+        while let Some((pair, c)) = self.peek() {
+            if c != count {
+                break;
+            }
+            self.heap.pop();
+            if !tied.contains(&pair) {
+                tied.push(pair);
+            }
+        }
+
+        // ...and put them back once the smallest is known
+        let best = tied
+            .iter()
+            .copied()
+            .min_by(|a, b| {
+                let key = |p: &Pair| (&self.vocab[&p.0], &self.vocab[&p.1]);
+                key(a).cmp(&key(b))
+            })
+            .expect("at least one pair is tied");
+        self.heap
+            .extend(tied.into_iter().map(|pair| PairCount { pair, count }));
+
+        Some((best, count))
+    }
+
+    fn push(&mut self, pair: Pair, count: u64) {
+        if let Some(max) = self.max_token_length {
+            if self.vocab[&pair.0].len() + self.vocab[&pair.1].len() > max {
+                return;
+            }
+        }
+        self.heap.push(PairCount { pair, count });
+    }
+
+    // highest count pair, dropping stale heap entries along the way
+    fn peek(&mut self) -> Option<(Pair, u64)> {
+        while let Some(top) = self.heap.peek() {
+            let current = self.pair_counts.get(&top.pair).copied().unwrap_or(0);
+            if current == top.count {
+                return Some((top.pair, top.count));
+            }
+
+            // stale, re-insert with its up to date count
+            let pair = top.pair;
+            self.heap.pop();
+            if current > 0 {
+                self.heap.push(PairCount {
+                    pair,
+                    count: current,
+                });
+            }
+        }
+        None
+    }
+
+    /// replaces every occurrence of `pair` with `new_token`
+    pub fn merge(&mut self, pair: Pair, new_token: Token) {
+        let bytes = [&self.vocab[&pair.0][..], &self.vocab[&pair.1][..]].concat();
+        self.vocab.insert(new_token, bytes);
+
+        let mut deltas: FxHashMap<Pair, i64> = FxHashMap::default();
+
+        // move the affected words out so they can be merged in parallel
+        let mut words: Vec<(usize, Vec<Token>)> = self
+            .pair_words
+            .remove(&pair)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|i| (i, std::mem::take(&mut self.words[i])))
+            .collect();
+        words.sort_unstable_by_key(|&(i, _)| i);
+
+        let changes: Vec<Vec<(Pair, i64)>> = words
+            .par_iter_mut()
+            .map(|(_, word)| merge_word(word, pair, new_token))
+            .collect();
+
+        // ...and fold the changes back in word order
+        for ((i, word), changes) in words.into_iter().zip(changes) {
+            let count = self.counts[i] as i64;
+            for (p, delta) in changes {
+                *deltas.entry(p).or_default() += delta * count;
+                if delta > 0 {
+                    self.pair_words.entry(p).or_default().insert(i);
+                }
+            }
+            self.words[i] = word;
+        }
+
+        self.pair_counts.remove(&pair);
+        for (p, delta) in deltas {
+            if p == pair || delta == 0 {
+                continue;
+            }
+            let count = self.pair_counts.entry(p).or_default();
+            *count = (*count as i64 + delta) as u64;
+
+            if *count == 0 {
+                self.pair_counts.remove(&p);
+            } else if delta > 0 {
+                let count = *count;
+                self.push(p, count);
+            }
+        }
+    }
+}
+
+/// dedicated pool of `num_threads` threads, or rayon's global pool when `None`
+pub(crate) struct ThreadPool(Option<rayon::ThreadPool>);
+
+impl ThreadPool {
+    pub fn new(num_threads: Option<usize>) -> Result<Self> {
+        let pool = num_threads
+            .map(|n| rayon::ThreadPoolBuilder::new().num_threads(n).build())
+            .transpose()?;
+        Ok(ThreadPool(pool))
+    }
+
+    pub fn install<R, F>(&self, f: F) -> R
+    where
+        R: Send,
+        F: FnOnce() -> R + Send,
+    {
+        match &self.0 {
+            Some(pool) => pool.install(f),
+            None => f(),
+        }
+    }
+}
+
+/// sums two sets of word counts
+pub fn merge_counts(a: WordCounts, b: WordCounts) -> WordCounts {
+    let (mut into, from) = if a.len() >= b.len() { (a, b) } else { (b, a) };
+    for (word, count) in from {
+        *into.entry(word).or_default() += count;
+    }
+    into
+}
+
+/// merges `pair` within a single word, returning the resulting pair count changes
+fn merge_word(word: &mut Vec<Token>, pair: Pair, new_token: Token) -> Vec<(Pair, i64)> {
+    let mut changes = Vec::new();
+    let mut merged = Vec::with_capacity(word.len());
+
+    let mut i = 0;
+    while i < word.len() {
+        if i + 1 < word.len() && (word[i], word[i + 1]) == pair {
+            if let Some(&prev) = merged.last() {
+                changes.push(((prev, pair.0), -1));
+                changes.push(((prev, new_token), 1));
+            }
+            if let Some(&next) = word.get(i + 2) {
+                changes.push(((pair.1, next), -1));
+                changes.push(((new_token, next), 1));
+            }
+            merged.push(new_token);
+            i += 2;
+        } else {
+            merged.push(word[i]);
+            i += 1;
+        }
+    }
+
+    *word = merged;
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merge_word_tracks_pair_changes() {
+        let mut word = vec![1, 1, 1, 1, 2];
+        let changes = merge_word(&mut word, (1, 1), 3);
+        assert_eq!(word, vec![3, 3, 2]);
+
+        let mut net: FxHashMap<Pair, i64> = FxHashMap::default();
+        for (p, delta) in changes {
+            *net.entry(p).or_default() += delta;
+        }
+        net.retain(|&p, d| *d != 0 && p != (1, 1));
+
+        // (1, 1) x3 and (1, 2) become (3, 3) and (3, 2)
+        assert_eq!(
+            net,
+            FxHashMap::from_iter(vec![((3, 3), 1), ((1, 2), -1), ((3, 2), 1)])
+        );
+    }
+
+    #[test]
+    fn ties_break_on_bytes_not_ids() {
+        // id 300 sorts after 98 ('b') but its bytes "a" sort first
+        let vocab = FxHashMap::from_iter(vec![
+            (97, b"a".to_vec()),
+            (98, b"b".to_vec()),
+            (300, b"a".to_vec()),
+        ]);
+        let words = vec![(vec![98, 97], 2), (vec![300, 98], 2)];
+        let mut trainer = Trainer::new(words, vocab, None);
+
+        assert_eq!(trainer.best(), Some(((300, 98), 2)));
+    }
+}
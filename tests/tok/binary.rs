@@ -0,0 +1,117 @@
+use rstest::*;
+use tempdir::TempDir;
+use toktkn::binary::{BINARY_VERSION, MAGIC};
+use toktkn::{
+    config::TokenizerConfig, BPETokenizer, MappedBPETokenizer, Pretrained, Tokenizer,
+    TokenizerError,
+};
+
+use crate::helpers::{get_corpus, get_sentence, tmpdir};
+
+const GPT2_TINY: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/gpt2_tiny.json");
+
+#[fixture]
+#[once]
+fn tokenizer_with_specials() -> BPETokenizer {
+    let mut tok = BPETokenizer::new(TokenizerConfig::new(100, None));
+    tok.add_special_tokens(vec!["<s>", "</s>", "<|ünï|>"]);
+    tok.train(&get_corpus());
+    tok
+}
+
+#[rstest]
+fn test_binary_roundtrip_by_extension(tmpdir: &TempDir, tokenizer_with_specials: &BPETokenizer) {
+    let tok = tokenizer_with_specials;
+    let binary = tmpdir.path().join("tokenizer.tkn");
+    let json = tmpdir.path().join("tokenizer_for_size.json");
+    tok.save_file(&binary).unwrap();
+    tok.save_pretrained(&json).unwrap();
+
+    let bytes = std::fs::read(&binary).unwrap();
+    assert_eq!(&bytes[..4], MAGIC);
+    assert!(bytes.len() < std::fs::metadata(&json).unwrap().len() as usize);
+
+    let loaded = BPETokenizer::from_file(&binary).unwrap();
+    assert_eq!(loaded.encoder, tok.encoder);
+    assert_eq!(loaded.config.special_tokens_map, tok.config.special_tokens_map);
+    assert_eq!(loaded.config.vocab_size, tok.config.vocab_size);
+    assert_eq!(loaded.config.pretokenizer, tok.config.pretokenizer);
+
+    let text = format!("<s>{}<|ünï|></s>", get_sentence());
+    assert_eq!(loaded.encode(&text), tok.encode(&text));
+    // same tokenizer, same bytes
+    assert_eq!(loaded.to_bytes().unwrap(), bytes);
+}
+
+#[test]
+fn test_binary_keeps_imported_ids() {
+    let gpt2 = BPETokenizer::from_hf(GPT2_TINY).unwrap();
+    let loaded = BPETokenizer::from_bytes(&gpt2.to_bytes().unwrap()).unwrap();
+
+    let text = " the and of<|endoftext|>";
+    assert_eq!(loaded.encode(text), vec![262, 290, 286, 50256]);
+    assert_eq!(loaded.encode(text), gpt2.encode(text));
+}
+
+#[rstest]
+fn test_binary_rejects_newer_versions(tokenizer_with_specials: &BPETokenizer) {
+    let mut bytes = tokenizer_with_specials.to_bytes().unwrap();
+    bytes[4..8].copy_from_slice(&(BINARY_VERSION + 1).to_le_bytes());
+    assert!(matches!(
+        BPETokenizer::from_bytes(&bytes),
+        Err(TokenizerError::UnsupportedVersion { found, supported })
+            if found == BINARY_VERSION + 1 && supported == BINARY_VERSION
+    ));
+
+    bytes[4..8].copy_from_slice(&0u32.to_le_bytes());
+    assert!(matches!(BPETokenizer::from_bytes(&bytes), Err(TokenizerError::Corrupt(_))));
+
+    let json = serde_json::to_vec(tokenizer_with_specials).unwrap();
+    assert!(matches!(BPETokenizer::from_bytes(&json), Err(TokenizerError::Corrupt(_))));
+}
+
+#[rstest]
+fn test_binary_truncated_files_error(tokenizer_with_specials: &BPETokenizer) {
+    let bytes = tokenizer_with_specials.to_bytes().unwrap();
+    for len in 0..bytes.len() {
+        assert!(BPETokenizer::from_bytes(&bytes[..len]).is_err(), "truncated to {}", len);
+    }
+}
+
+#[rstest]
+fn test_binary_corrupt_files_dont_panic(tmpdir: &TempDir, tokenizer_with_specials: &BPETokenizer) {
+    // xorshift, so failures reproduce
+    let mut state = 0x2545f4914f6cdd1d_u64;
+    let mut next = move || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state
+    };
+
+    let gpt2 = BPETokenizer::from_hf(GPT2_TINY).unwrap();
+    let path = tmpdir.path().join("fuzz.tkn");
+    for tok in [tokenizer_with_specials, &gpt2] {
+        let bytes = tok.to_bytes().unwrap();
+
+        for _ in 0..1000 {
+            let mut corrupt = bytes.clone();
+            for _ in 0..1 + next() % 4 {
+                let i = next() as usize % corrupt.len();
+                corrupt[i] = next() as u8;
+            }
+
+            // whatever still loads has to be usable, mapped or not
+            if let Ok(tok) = BPETokenizer::from_bytes(&corrupt) {
+                std::fs::write(&path, &corrupt).unwrap();
+                let mapped = MappedBPETokenizer::open(&path).unwrap();
+
+                let text = "some text <s> to encode<|endoftext|>";
+                assert_eq!(mapped.encode(text), tok.encode(text));
+                let ids: Vec<u32> = (0..400).chain(50000..50300).collect();
+                tok.decode_lossy(&ids);
+                mapped.decode_lossy(&ids);
+            }
+        }
+    }
+}
@@ -0,0 +1,157 @@
+use rstest::*;
+use std::ops::ControlFlow;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use tempdir::TempDir;
+use toktkn::{
+    config::TokenizerConfig, BPETokenizer, Checkpoint, Pretrained, SilentObserver, Tokenizer,
+    TokenizerError, TrainingObserver,
+};
+
+use crate::helpers::{get_corpus, tmpdir};
+
+// simulates the process dying after `at` merges
+struct Crash {
+    at: usize,
+}
+
+impl TrainingObserver for Crash {
+    fn on_progress(&mut self, done: usize, _total: usize) -> ControlFlow<()> {
+        if done == self.at {
+            panic!("crashed after {} merges", done);
+        }
+        ControlFlow::Continue(())
+    }
+}
+
+#[rstest]
+fn test_resume_matches_uninterrupted_run(tmpdir: &TempDir) {
+    let corpus = get_corpus();
+    let path = tmpdir.path().join("resume.ckpt");
+
+    let mut expected = BPETokenizer::new(TokenizerConfig::new(150, None));
+    expected.add_special_tokens(vec!["<eos>"]);
+    expected.train(&corpus);
+
+    let mut tok = BPETokenizer::new(TokenizerConfig::new(150, None));
+    tok.add_special_tokens(vec!["<eos>"]);
+    let crashed = catch_unwind(AssertUnwindSafe(|| {
+        tok.try_train_with_checkpoints(&corpus, &path, 40, &mut Crash { at: 100 })
+    }));
+    assert!(crashed.is_err());
+
+    // the last checkpoint was written after 80 merges
+    let checkpoint = Checkpoint::from_pretrained(&path).unwrap();
+    assert_eq!(checkpoint.tokenizer.encoder.len(), 80);
+
+    let (tok, report) =
+        BPETokenizer::resume_training_with_observer(&path, &corpus, &mut SilentObserver).unwrap();
+    assert_eq!(report.merges.len(), 149 - 80);
+    assert_eq!(tok.encoder, expected.encoder);
+    assert_eq!(tok.config.special_tokens_map, expected.config.special_tokens_map);
+
+    // the finished run leaves a checkpoint holding every merge
+    let checkpoint = Checkpoint::from_pretrained(&path).unwrap();
+    assert_eq!(checkpoint.tokenizer.encoder, expected.encoder);
+}
+
+#[rstest]
+fn test_resume_on_other_corpus_fails(tmpdir: &TempDir) {
+    let path = tmpdir.path().join("mismatch.ckpt");
+
+    let mut tok = BPETokenizer::new(TokenizerConfig::new(40, None));
+    tok.try_train_with_checkpoints(&get_corpus(), &path, 10, &mut SilentObserver)
+        .unwrap();
+
+    let resumed = BPETokenizer::resume_training(&path, "some other corpus");
+    assert!(matches!(resumed, Err(TokenizerError::CheckpointMismatch)));
+}
+
+#[rstest]
+fn test_zero_checkpoint_interval_fails(tmpdir: &TempDir) {
+    let path = tmpdir.path().join("zero.ckpt");
+
+    let mut tok = BPETokenizer::new(TokenizerConfig::new(40, None));
+    let result = tok.try_train_with_checkpoints(&get_corpus(), &path, 0, &mut SilentObserver);
+    assert!(matches!(result, Err(TokenizerError::InvalidArgument(_))));
+    assert!(tok.encoder.is_empty());
+    assert!(!path.exists());
+}
+
+#[rstest]
+fn test_failed_checkpoint_rolls_back(tmpdir: &TempDir) {
+    let path = tmpdir.path().join("missing").join("bpe.ckpt");
+
+    let mut tok = BPETokenizer::new(TokenizerConfig::new(60, None));
+    tok.train(&get_corpus());
+    let before = tok.encoder.clone();
+
+    tok.config.vocab_size = 100;
+    let result = tok.try_train_with_checkpoints(&get_corpus(), &path, 10, &mut SilentObserver);
+    assert!(matches!(result, Err(TokenizerError::Io(_))));
+    assert_eq!(tok.encoder, before);
+    assert_eq!(tok.config.vocab_size, 100);
+    assert_eq!(tok.decode(&tok.encode("hello world")), "hello world");
+}
+
+#[rstest]
+fn test_checkpoint_keeps_files_sharing_its_stem(tmpdir: &TempDir) {
+    let path = tmpdir.path().join("bpe.ckpt");
+    let other = tmpdir.path().join("bpe.tmp");
+    std::fs::write(&other, "not a checkpoint").unwrap();
+
+    let mut tok = BPETokenizer::new(TokenizerConfig::new(40, None));
+    tok.try_train_with_checkpoints(&get_corpus(), &path, 10, &mut SilentObserver)
+        .unwrap();
+
+    assert_eq!(std::fs::read_to_string(&other).unwrap(), "not a checkpoint");
+    assert!(!tmpdir.path().join("bpe.ckpt.tmp").exists());
+}
+
+#[rstest]
+fn test_resume_from_files_and_iterator(tmpdir: &TempDir) {
+    let texts: Vec<String> = (0..4).map(|_| get_corpus()).collect();
+    let files: Vec<_> = texts
+        .iter()
+        .enumerate()
+        .map(|(i, text)| {
+            let file = tmpdir.path().join(format!("corpus_{}.txt", i));
+            std::fs::write(&file, text).unwrap();
+            file
+        })
+        .collect();
+
+    let mut expected = BPETokenizer::new(TokenizerConfig::new(150, None));
+    expected
+        .train_from_files_with_observer(&files, &mut SilentObserver)
+        .unwrap();
+
+    // from files
+    let path = tmpdir.path().join("files.ckpt");
+    let mut tok = BPETokenizer::new(TokenizerConfig::new(150, None));
+    let crashed = catch_unwind(AssertUnwindSafe(|| {
+        tok.train_from_files_with_checkpoints(&files, &path, 40, &mut Crash { at: 100 })
+    }));
+    assert!(crashed.is_err());
+
+    let (tok, _) = BPETokenizer::resume_training_from_files(&path, &files, &mut SilentObserver).unwrap();
+    assert_eq!(tok.encoder, expected.encoder);
+
+    // from an iterator
+    let path = tmpdir.path().join("iterator.ckpt");
+    let mut tok = BPETokenizer::new(TokenizerConfig::new(150, None));
+    let crashed = catch_unwind(AssertUnwindSafe(|| {
+        tok.train_from_iterator_with_checkpoints(&texts, &path, 40, &mut Crash { at: 100 })
+    }));
+    assert!(crashed.is_err());
+
+    let (tok, _) =
+        BPETokenizer::resume_training_from_iterator(&path, &texts, &mut SilentObserver).unwrap();
+    let mut expected = BPETokenizer::new(TokenizerConfig::new(150, None));
+    expected
+        .train_from_iterator_with_observer(&texts, &mut SilentObserver)
+        .unwrap();
+    assert_eq!(tok.encoder, expected.encoder);
+
+    let resumed = BPETokenizer::resume_training_from_iterator(&path, &texts[1..], &mut SilentObserver);
+    assert!(matches!(resumed, Err(TokenizerError::CheckpointMismatch)));
+}
@@ -0,0 +1,105 @@
+use rstest::*;
+use rustc_hash::FxHashMap;
+use tempdir::TempDir;
+use toktkn::pretokenizer::PreTokenizer;
+use toktkn::{BPETokenizer, MappedBPETokenizer, Pretrained, TokenizerError};
+
+use crate::helpers::{get_corpus, tmpdir, tokenizer};
+
+const GPT2_TINY: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/gpt2_tiny.json");
+
+#[rstest]
+fn test_fingerprint_is_pinned() {
+    // changing what goes into the hash breaks every recorded fingerprint
+    let gpt2 = BPETokenizer::from_hf(GPT2_TINY).unwrap();
+    assert_eq!(gpt2.fingerprint(), "e3c18bd86dee80e76397f13dd73bdfbc47c86971b066db31f9fbf9001a7a6380");
+}
+
+#[rstest]
+fn test_fingerprint_ignores_map_order(tmpdir: &TempDir, tokenizer: &BPETokenizer) {
+    let path = tmpdir.path().join("fingerprint_order.json");
+    tokenizer.save_pretrained(&path).unwrap();
+    let mut reordered = BPETokenizer::from_pretrained(&path).unwrap();
+
+    let mut merges: Vec<_> = tokenizer.encoder.iter().map(|(&p, &id)| (p, id)).collect();
+    merges.sort_unstable_by_key(|&(_, id)| std::cmp::Reverse(id));
+    reordered.encoder = FxHashMap::from_iter(merges);
+
+    assert_eq!(reordered.fingerprint(), tokenizer.fingerprint());
+}
+
+#[rstest]
+fn test_fingerprint_survives_saving(tmpdir: &TempDir, tokenizer: &BPETokenizer) {
+    let json = tmpdir.path().join("fingerprint.json");
+    let binary = tmpdir.path().join("fingerprint.tkn");
+    tokenizer.save_pretrained(&json).unwrap();
+    tokenizer.save_file(&binary).unwrap();
+
+    let expected = tokenizer.fingerprint();
+    assert_eq!(expected.len(), 64);
+    assert_eq!(BPETokenizer::from_pretrained(&json).unwrap().fingerprint(), expected);
+    assert_eq!(BPETokenizer::from_file(&binary).unwrap().fingerprint(), expected);
+    assert_eq!(MappedBPETokenizer::open(&binary).unwrap().fingerprint(), expected);
+}
+
+#[rstest]
+fn test_fingerprint_tracks_tokenization(tmpdir: &TempDir, tokenizer: &BPETokenizer) {
+    let path = tmpdir.path().join("fingerprint_changes.json");
+    tokenizer.save_pretrained(&path).unwrap();
+    let load = || BPETokenizer::from_pretrained(&path).unwrap();
+    let expected = tokenizer.fingerprint();
+
+    // training settings don't change the output
+    let mut tok = load();
+    tok.config.vocab_size += 1;
+    tok.config.min_frequency = Some(3);
+    assert_eq!(tok.fingerprint(), expected);
+
+    let mut tok = load();
+    tok.config.pretokenizer = PreTokenizer::Cl100k;
+    assert_ne!(tok.fingerprint(), expected);
+
+    let mut tok = load();
+    tok.add_special_tokens(vec!["<s>"]);
+    assert_ne!(tok.fingerprint(), expected);
+
+    let mut tok = load();
+    tok.extend(&get_corpus(), 1).unwrap();
+    assert_ne!(tok.fingerprint(), expected);
+}
+
+#[rstest]
+fn test_fingerprint_checked_on_load(tmpdir: &TempDir, tokenizer: &BPETokenizer) {
+    let path = tmpdir.path().join("fingerprint_tampered.json");
+    tokenizer.save_pretrained(&path).unwrap();
+    let mut json: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    assert_eq!(json["fingerprint"], tokenizer.fingerprint());
+
+    // swap the last merge for a different pair
+    let last = json["encoder"].as_array_mut().unwrap().last_mut().unwrap();
+    last[0] = serde_json::json!(["33", "33"]);
+    std::fs::write(&path, json.to_string()).unwrap();
+    assert!(matches!(
+        BPETokenizer::from_file(&path),
+        Err(TokenizerError::FingerprintMismatch { .. })
+    ));
+
+    // files saved before fingerprints existed still load
+    json.as_object_mut().unwrap().remove("fingerprint");
+    std::fs::write(&path, json.to_string()).unwrap();
+    assert!(BPETokenizer::from_file(&path).is_ok());
+
+    let path = tmpdir.path().join("fingerprint_tampered.tkn");
+    let mut bytes = tokenizer.to_bytes().unwrap();
+    bytes[8] ^= 1;
+    std::fs::write(&path, &bytes).unwrap();
+    assert!(matches!(
+        BPETokenizer::from_file(&path),
+        Err(TokenizerError::FingerprintMismatch { .. })
+    ));
+    assert!(matches!(
+        MappedBPETokenizer::open(&path),
+        Err(TokenizerError::FingerprintMismatch { .. })
+    ));
+}
@@ -0,0 +1,92 @@
+use rstest::*;
+use tempdir::TempDir;
+use toktkn::{gpt2::bytes_to_unicode, pretokenizer::PreTokenizer, BPETokenizer, Tokenizer, TokenizerError};
+
+use crate::helpers::{get_sentence, tmpdir};
+
+// the same cut down gpt2 as `gpt2_tiny.json`, in the original vocab.json and merges.txt
+const VOCAB: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/gpt2_tiny_vocab.json");
+const MERGES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/gpt2_tiny_merges.txt");
+const GPT2_TINY: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/gpt2_tiny.json");
+
+#[fixture]
+#[once]
+fn gpt2() -> BPETokenizer {
+    BPETokenizer::from_vocab_merges(VOCAB, MERGES, &["<|endoftext|>"]).unwrap()
+}
+
+#[test]
+fn test_bytes_to_unicode() {
+    let chars = bytes_to_unicode();
+    assert_eq!(chars[b'a' as usize], 'a');
+    assert_eq!(chars[b' ' as usize], 'Ġ');
+    assert_eq!(chars[b'\n' as usize], 'Ċ');
+    assert_eq!(chars[0xad], 'Ń');
+}
+
+#[rstest]
+#[case(" the and of in to a\n", vec![262, 290, 286, 287, 284, 257, 198])]
+#[case("<|endoftext|>", vec![50256])]
+#[case("Hello", vec![39, 68, 75, 75, 78])]
+#[case("é", vec![127, 102])]
+fn test_vocab_merges_gpt2_ids(gpt2: &BPETokenizer, #[case] text: &str, #[case] ids: Vec<u32>) {
+    assert_eq!(gpt2.encode(text), ids);
+    assert_eq!(gpt2.decode(&ids), text);
+}
+
+#[rstest]
+fn test_vocab_merges_matches_hf(gpt2: &BPETokenizer) {
+    let hf = BPETokenizer::from_hf(GPT2_TINY).unwrap();
+    assert_eq!(gpt2.config.pretokenizer, PreTokenizer::Gpt2);
+    for _ in 0..10 {
+        let text = format!("{}<|endoftext|>", get_sentence());
+        assert_eq!(gpt2.encode(&text), hf.encode(&text));
+    }
+}
+
+#[rstest]
+fn test_save_vocab_merges_reproduces_files(gpt2: &BPETokenizer, tmpdir: &TempDir) {
+    let vocab = tmpdir.path().join("gpt2_tiny_vocab.json");
+    let merges = tmpdir.path().join("gpt2_tiny_merges.txt");
+    gpt2.save_vocab_merges(&vocab, &merges).unwrap();
+
+    let read = |path| std::fs::read_to_string(path).unwrap();
+    assert_eq!(read(vocab.as_path()), read(std::path::Path::new(VOCAB)));
+    assert_eq!(read(merges.as_path()), read(std::path::Path::new(MERGES)));
+}
+
+#[rstest]
+// no separator
+#[case("#version: 0.2\nĠt\n")]
+// "x" + "y" isn't in the vocab
+#[case("x y\n")]
+// unknown left side
+#[case("Ġ zz\n")]
+fn test_vocab_merges_rejects_bad_merges(#[case] merges: &str) {
+    let vocab = std::fs::read_to_string(VOCAB).unwrap();
+    let tok = BPETokenizer::from_vocab_merges_str(&vocab, merges, &[]);
+    assert!(matches!(tok, Err(TokenizerError::Unsupported(_))));
+}
+
+#[test]
+fn test_vocab_merges_only_listed_specials() {
+    let mut vocab: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(VOCAB).unwrap()).unwrap();
+    // no merge produces it, but it isn't special either
+    vocab["Ġunreached"] = 60000.into();
+    let vocab = vocab.to_string();
+    let merges = std::fs::read_to_string(MERGES).unwrap();
+
+    let tok = BPETokenizer::from_vocab_merges_str(&vocab, &merges, &["<|endoftext|>"]).unwrap();
+    let specials = tok.config.special_tokens_map.as_ref().unwrap();
+    assert_eq!(specials.keys().collect::<Vec<_>>(), vec!["<|endoftext|>"]);
+    assert!(!tok.encode(" unreached").contains(&60000));
+    assert_eq!(tok.encode("<|endoftext|>"), vec![50256]);
+
+    // without it `<|endoftext|>` is plain text
+    let tok = BPETokenizer::from_vocab_merges_str(&vocab, &merges, &[]).unwrap();
+    assert!(tok.config.special_tokens_map.is_none());
+    assert_ne!(tok.encode("<|endoftext|>"), vec![50256]);
+
+    let missing = BPETokenizer::from_vocab_merges_str(&vocab, &merges, &["<pad>"]);
+    assert!(matches!(missing, Err(TokenizerError::InvalidArgument(_))));
+}
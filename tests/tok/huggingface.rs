@@ -0,0 +1,123 @@
+use rstest::*;
+use tempdir::TempDir;
+use toktkn::{
+    config::TokenizerConfig, pretokenizer::PreTokenizer, BPETokenizer, Pretrained, SilentObserver,
+    Tokenizer, TokenizerError,
+};
+
+use crate::helpers::{get_corpus, get_sentence, tmpdir};
+
+// gpt2 cut down to its first 35 merges
+const GPT2_TINY: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/gpt2_tiny.json");
+
+#[fixture]
+#[once]
+fn gpt2() -> BPETokenizer {
+    BPETokenizer::from_hf(GPT2_TINY).unwrap()
+}
+
+#[rstest]
+// words fully merged within the fixture's merges, same ids as the full gpt2
+#[case(" the and of in to a\n", vec![262, 290, 286, 287, 284, 257, 198])]
+#[case("<|endoftext|>", vec![50256])]
+// partially merged or raw bytes in gpt2's byte order
+#[case(" sing", vec![264, 278])]
+#[case("Hello", vec![39, 68, 75, 75, 78])]
+#[case("é", vec![127, 102])]
+fn test_hf_gpt2_ids(gpt2: &BPETokenizer, #[case] text: &str, #[case] ids: Vec<u32>) {
+    assert_eq!(gpt2.encode(text), ids);
+    assert_eq!(gpt2.encode_sequential(text).unwrap(), ids);
+    assert_eq!(gpt2.decode(&ids), text);
+}
+
+#[rstest]
+fn test_hf_unknown_ids(gpt2: &BPETokenizer) {
+    // the fixture has no token 1000 even though 1000 < 50256
+    let err = gpt2.try_decode(&[262, 1000]);
+    assert!(matches!(err, Err(TokenizerError::UnknownToken(1000))));
+    assert_eq!(gpt2.id_to_bytes(220), Some(b" ".to_vec()));
+    assert_eq!(gpt2.id_to_bytes(1000), None);
+}
+
+#[rstest]
+fn test_hf_roundtrips_through_pretrained(gpt2: &BPETokenizer, tmpdir: &TempDir) {
+    let path = tmpdir.path().join("gpt2_tiny_toktkn.json");
+    gpt2.save_pretrained(&path).unwrap();
+    let tok = BPETokenizer::from_pretrained(&path).unwrap();
+
+    let text = "the cat sat on the mat<|endoftext|>";
+    assert_eq!(tok.encode(text), gpt2.encode(text));
+    assert_eq!(tok.decode(&gpt2.encode(text)), text);
+}
+
+#[test]
+fn test_hf_extend_allocates_new_ids() {
+    let mut tok = BPETokenizer::from_hf(GPT2_TINY).unwrap();
+    let report = tok
+        .extend_with_observer(&" thermal ".repeat(20), 3, &mut SilentObserver)
+        .unwrap();
+
+    let ids: Vec<u32> = report.merges.iter().map(|&(_, id)| id).collect();
+    assert_eq!(ids, vec![50257, 50258, 50259]);
+    assert_eq!(tok.decode(&tok.encode(" thermal")), " thermal");
+}
+
+#[rstest]
+#[case(r#"{"type": "ByteLevel", "add_prefix_space": true, "use_regex": true}"#)]
+#[case(r#"{"type": "Whitespace"}"#)]
+fn test_hf_unsupported_pre_tokenizer(#[case] pre_tokenizer: &str) {
+    let mut json: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(GPT2_TINY).unwrap()).unwrap();
+    json["pre_tokenizer"] = serde_json::from_str(pre_tokenizer).unwrap();
+
+    let tok = BPETokenizer::from_hf_str(&json.to_string());
+    assert!(matches!(tok, Err(TokenizerError::Unsupported(_))));
+}
+
+#[rstest]
+#[case(PreTokenizer::Gpt2)]
+#[case(PreTokenizer::Cl100k)]
+#[case(PreTokenizer::None)]
+fn test_save_hf_roundtrip(tmpdir: &TempDir, #[case] pretokenizer: PreTokenizer) {
+    let mut config = TokenizerConfig::new(80, None);
+    config.pretokenizer = pretokenizer;
+    let mut tok = BPETokenizer::new(config);
+    tok.add_special_tokens(vec!["<s>", "</s>"]);
+    tok.train(&get_corpus());
+
+    let path = tmpdir.path().join(format!("hf_{:?}.json", pretokenizer));
+    tok.save_hf(&path).unwrap();
+
+    let json: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    let model = &json["model"];
+    assert_eq!(model["type"], "BPE");
+    assert_eq!(model["vocab"].as_object().unwrap().len(), 256 + tok.encoder.len() + 2);
+    assert_eq!(model["merges"].as_array().unwrap().len(), tok.encoder.len());
+    for merge in model["merges"].as_array().unwrap() {
+        let (l, r) = merge.as_str().unwrap().split_once(' ').unwrap();
+        assert!(model["vocab"].get(l).is_some() && model["vocab"].get(r).is_some());
+    }
+    assert_eq!(json["added_tokens"][0]["content"], "<s>");
+    assert_eq!(json["added_tokens"][0]["id"], 256);
+    assert_eq!(json["added_tokens"][1]["content"], "</s>");
+    assert!(json["pre_tokenizer"]["type"].is_string());
+
+    let hf = BPETokenizer::from_hf(&path).unwrap();
+    assert_eq!(hf.config.pretokenizer, pretokenizer);
+    for _ in 0..10 {
+        let text = format!("<s>{}</s>", get_sentence());
+        assert_eq!(hf.encode(&text), tok.encode(&text));
+    }
+}
+
+#[rstest]
+fn test_save_hf_reproduces_imported_file(gpt2: &BPETokenizer) {
+    let original: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(GPT2_TINY).unwrap()).unwrap();
+    let exported = gpt2.to_hf_json().unwrap();
+
+    assert_eq!(exported["model"]["vocab"], original["model"]["vocab"]);
+    assert_eq!(exported["model"]["merges"], original["model"]["merges"]);
+    assert_eq!(exported["added_tokens"][0]["id"], 50256);
+    assert_eq!(exported["pre_tokenizer"], original["pre_tokenizer"]);
+}
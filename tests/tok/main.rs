@@ -1,3 +1,12 @@
 mod tokenizer;
 mod serialize;
 mod helpers;
+mod pretokenizer;
+mod stream;
+mod checkpoint;
+mod huggingface;
+mod tiktoken;
+mod gpt2;
+mod binary;
+mod mapped;
+mod fingerprint;
@@ -0,0 +1,63 @@
+use rstest::*;
+use tempdir::TempDir;
+use toktkn::{config::TokenizerConfig, BPETokenizer, MappedBPETokenizer, Tokenizer, TokenizerError};
+
+use crate::helpers::{get_corpus, get_sentence, tmpdir};
+
+const GPT2_TINY: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/gpt2_tiny.json");
+
+#[rstest]
+fn test_mapped_matches_tokenizer(tmpdir: &TempDir) {
+    let mut tok = BPETokenizer::new(TokenizerConfig::new(120, None));
+    tok.add_special_tokens(vec!["<s>", "</s>"]);
+    tok.train(&get_corpus());
+
+    let path = tmpdir.path().join("mapped.tkn");
+    tok.save_file(&path).unwrap();
+    let mapped = MappedBPETokenizer::open(&path).unwrap();
+    assert_eq!(mapped.len(), tok.len());
+
+    for _ in 0..10 {
+        let text = format!("<s>{}</s>", get_sentence());
+        let ids = tok.encode(&text);
+        assert_eq!(mapped.encode(&text), ids);
+        assert_eq!(mapped.decode(&ids), text);
+    }
+    for id in 0..(tok.len() + 256) as u32 {
+        assert_eq!(mapped.id_to_bytes(id), tok.id_to_bytes(id));
+    }
+
+    let unknown = (tok.len() + 256) as u32;
+    assert!(matches!(mapped.try_decode(&[unknown]), Err(TokenizerError::UnknownToken(id)) if id == unknown));
+    assert_eq!(mapped.id_to_bytes(unknown), None);
+}
+
+#[rstest]
+fn test_mapped_keeps_imported_ids(tmpdir: &TempDir) {
+    let gpt2 = BPETokenizer::from_hf(GPT2_TINY).unwrap();
+    let path = tmpdir.path().join("gpt2_tiny.tkn");
+    gpt2.save_file(&path).unwrap();
+    let mapped = MappedBPETokenizer::open(&path).unwrap();
+
+    let text = " the and of in to a\nHello<|endoftext|>";
+    assert_eq!(mapped.encode(text), gpt2.encode(text));
+    assert_eq!(mapped.decode(&gpt2.encode(text)), text);
+    // below the largest id but never allocated
+    assert!(matches!(mapped.try_decode(&[1000]), Err(TokenizerError::UnknownToken(1000))));
+}
+
+#[rstest]
+fn test_mapped_rejects_old_and_corrupt_files(tmpdir: &TempDir) {
+    // without merges v1 only differs in its version number and missing fingerprint
+    let mut bytes = BPETokenizer::new(TokenizerConfig::new(10, None)).to_bytes().unwrap();
+    bytes[4..8].copy_from_slice(&1u32.to_le_bytes());
+    bytes.drain(8..40);
+    let path = tmpdir.path().join("v1.tkn");
+    std::fs::write(&path, &bytes).unwrap();
+    assert!(BPETokenizer::from_file(&path).is_ok());
+    assert!(matches!(MappedBPETokenizer::open(&path), Err(TokenizerError::Unsupported(_))));
+
+    let path = tmpdir.path().join("corrupt.tkn");
+    std::fs::write(&path, &bytes[..bytes.len() - 1]).unwrap();
+    assert!(matches!(MappedBPETokenizer::open(&path), Err(TokenizerError::Corrupt(_))));
+}
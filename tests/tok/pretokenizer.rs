@@ -0,0 +1,61 @@
+use rstest::*;
+use toktkn::{
+    config::TokenizerConfig,
+    pretokenizer::PreTokenizer,
+    BPETokenizer, Tokenizer,
+};
+
+use crate::helpers::{get_corpus, get_sentence};
+
+#[rstest]
+fn test_pre_tokenize_is_lossless(
+    #[values(
+        PreTokenizer::None,
+        PreTokenizer::Whitespace,
+        PreTokenizer::Punctuation,
+        PreTokenizer::Digits,
+        PreTokenizer::Gpt2,
+        PreTokenizer::Cl100k,
+        PreTokenizer::O200k
+    )]
+    pretokenizer: PreTokenizer,
+) {
+    let text = String::from("it's 2024,  rust is\tpretty fun 🦀!\n\n") + &get_sentence();
+    let pieces = pretokenizer.into_strategy().pre_tokenize(&text).unwrap();
+
+    assert_eq!(pieces.concat(), text);
+}
+
+#[test]
+fn test_gpt2_pattern() {
+    let pieces = PreTokenizer::Gpt2
+        .into_strategy()
+        .pre_tokenize("Hello world's 123!!")
+        .unwrap();
+
+    assert_eq!(pieces, vec!["Hello", " world", "'s", " 123", "!!"]);
+}
+
+#[test]
+fn test_o200k_pattern() {
+    let pieces = PreTokenizer::O200k
+        .into_strategy()
+        .pre_tokenize("HelloWorld 12345!!")
+        .unwrap();
+
+    assert_eq!(pieces, vec!["Hello", "World", " ", "123", "45", "!!"]);
+}
+
+#[test]
+fn test_merges_dont_cross_pre_tokens() {
+    let mut config = TokenizerConfig::new(60, None);
+    config.pretokenizer = PreTokenizer::Whitespace;
+    let mut tok = BPETokenizer::new(config);
+    tok.train(&get_corpus());
+
+    for &token in tok.encoder.values() {
+        let s = tok.decode(&[token]);
+        let spaces = s.chars().filter(|c| c.is_whitespace()).count();
+        assert!(spaces == 0 || spaces == s.chars().count(), "{:?}", s);
+    }
+}
@@ -3,13 +3,17 @@ extern crate tempdir;
 use tempdir::TempDir;
 use rstest::*;
 
-use toktkn::{config::TokenizerConfig, preproc::Normalizer, BPETokenizer, Pretrained};
+use rustc_hash::FxHashMap;
+use toktkn::{
+    config::TokenizerConfig, preproc::Normalizer, pretokenizer::PreTokenizer, BPETokenizer,
+    Pretrained, Tokenizer, TokenizerError,
+};
 
 // some test helpers
 use helpers::{tmpdir, tokenizer};
 
 #[rstest]
-fn test_serialize_config(tmpdir: &TempDir) -> std::io::Result<()> {
+fn test_serialize_config(tmpdir: &TempDir) -> Result<(), TokenizerError> {
     // arrange
     let file_path = tmpdir.path().join("config.txt");
     let mut config = TokenizerConfig::new(42, None);
@@ -21,12 +25,30 @@ fn test_serialize_config(tmpdir: &TempDir) -> std::io::Result<()> {
     config = TokenizerConfig::from_pretrained(&file_path)?;
     assert_eq!(config.vocab_size, 42);
     assert_eq!(config.preproc, Normalizer::default());
+    assert_eq!(config.pretokenizer, PreTokenizer::default());
 
     Ok(())
 }
 
 #[rstest]
-fn test_serialize_tokenizer(tmpdir: &TempDir, tokenizer: &BPETokenizer) -> std::io::Result<()> {
+fn test_serialize_config_without_pretokenizer(tmpdir: &TempDir) -> Result<(), TokenizerError> {
+    // arrange
+    let file_path = tmpdir.path().join("legacy_config.json");
+    std::fs::write(&file_path, r#"{"vocab_size":42,"special_tokens_map":null}"#)?;
+
+    // act
+    let config = TokenizerConfig::from_file(&file_path)?;
+    assert_eq!(config.pretokenizer, PreTokenizer::None);
+
+    // serde migrates the same way
+    let config = TokenizerConfig::from_pretrained(&file_path)?;
+    assert_eq!(config.pretokenizer, PreTokenizer::None);
+
+    Ok(())
+}
+
+#[rstest]
+fn test_serialize_tokenizer(tmpdir: &TempDir, tokenizer: &BPETokenizer) -> Result<(), TokenizerError> {
     // arrange
     let file_path = tmpdir.path().join("tokenizer.json");
 
@@ -40,3 +62,222 @@ fn test_serialize_tokenizer(tmpdir: &TempDir, tokenizer: &BPETokenizer) -> std::
 
     Ok(())
 }
+
+#[rstest]
+fn test_migrate_unversioned_tokenizer(tmpdir: &TempDir) -> Result<(), TokenizerError> {
+    // arrange: "hi" -> 128, "hi!" -> 129 and "<s>" -> 130 under the old id scheme
+    let file_path = tmpdir.path().join("legacy_tokenizer.json");
+    std::fs::write(
+        &file_path,
+        r#"{
+            "encoder": [[["104", "105"], "128"], [["128", "33"], "129"]],
+            "config": {"vocab_size": 3, "special_tokens_map": {"<s>": 130}}
+        }"#,
+    )?;
+
+    // act
+    let tokenizer = BPETokenizer::from_pretrained(&file_path)?;
+
+    assert_eq!(
+        tokenizer.encoder,
+        FxHashMap::from_iter(vec![((104, 105), 256), ((256, 33), 257)])
+    );
+    assert_eq!(tokenizer.encode("hi!<s>"), vec![257, 258]);
+    assert_eq!(tokenizer.decode(&[257, 258]), "hi!<s>");
+
+    Ok(())
+}
+
+#[rstest]
+fn test_serialize_tokenizer_writes_format_version(
+    tmpdir: &TempDir,
+    tokenizer: &BPETokenizer,
+) -> Result<(), TokenizerError> {
+    let file_path = tmpdir.path().join("versioned_tokenizer.json");
+    tokenizer.save_pretrained(&file_path)?;
+
+    let json: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&file_path)?)?;
+    assert_eq!(json["format_version"], toktkn::schema::FORMAT_VERSION);
+
+    Ok(())
+}
+
+#[rstest]
+fn test_from_pretrained_errors(tmpdir: &TempDir) -> Result<(), TokenizerError> {
+    let missing = BPETokenizer::from_pretrained(tmpdir.path().join("missing.json"));
+    assert!(matches!(missing, Err(TokenizerError::Io(_))));
+
+    let file_path = tmpdir.path().join("corrupt.json");
+    std::fs::write(&file_path, "{ not json")?;
+    let corrupt = BPETokenizer::from_pretrained(&file_path);
+    assert!(matches!(corrupt, Err(TokenizerError::Serialization(_))));
+
+    Ok(())
+}
+
+#[rstest]
+fn test_serialize_config_writes_format_version(tmpdir: &TempDir) -> Result<(), TokenizerError> {
+    let file_path = tmpdir.path().join("versioned_config.json");
+    TokenizerConfig::new(42, None).save_pretrained(&file_path)?;
+
+    let json: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&file_path)?)?;
+    assert_eq!(json["format_version"], toktkn::schema::FORMAT_VERSION);
+    assert_eq!(json["pretokenizer"], "Gpt2");
+
+    // only configs saved on their own carry a version, tokenizers version the whole file
+    let json: serde_json::Value = serde_json::to_value(BPETokenizer::new(TokenizerConfig::new(42, None)))?;
+    assert!(json["config"].get("format_version").is_none());
+
+    Ok(())
+}
+
+#[rstest]
+#[case("num_threads")]
+#[case("min_frequency")]
+#[case("max_token_length")]
+fn test_current_version_requires_every_field(
+    tmpdir: &TempDir,
+    tokenizer: &BPETokenizer,
+    #[case] field: &str,
+) -> Result<(), TokenizerError> {
+    // only files older than version 3 may leave fields out
+    let file_path = tmpdir.path().join(format!("config_without_{}.json", field));
+    TokenizerConfig::new(42, None).save_pretrained(&file_path)?;
+    let mut json: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&file_path)?)?;
+    json.as_object_mut().unwrap().remove(field);
+    std::fs::write(&file_path, json.to_string())?;
+    assert!(matches!(
+        TokenizerConfig::from_file(&file_path),
+        Err(TokenizerError::Serialization(_))
+    ));
+
+    let file_path = tmpdir.path().join(format!("tokenizer_without_{}.json", field));
+    tokenizer.save_file(&file_path)?;
+    let mut json: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&file_path)?)?;
+    json["config"].as_object_mut().unwrap().remove(field);
+    std::fs::write(&file_path, json.to_string())?;
+    assert!(matches!(
+        BPETokenizer::from_file(&file_path),
+        Err(TokenizerError::Serialization(_))
+    ));
+
+    Ok(())
+}
+
+#[rstest]
+fn test_migrate_v2_tokenizer(tmpdir: &TempDir) -> Result<(), TokenizerError> {
+    // arrange: config fields added before v3 were left out when unset
+    let file_path = tmpdir.path().join("v2_tokenizer.json");
+    std::fs::write(
+        &file_path,
+        r#"{
+            "format_version": 2,
+            "encoder": [[["104", "105"], "256"]],
+            "config": {"vocab_size": 1, "special_tokens_map": null}
+        }"#,
+    )?;
+
+    // act
+    let tokenizer = BPETokenizer::from_pretrained(&file_path)?;
+
+    assert_eq!(tokenizer.config.pretokenizer, PreTokenizer::None);
+    assert_eq!(tokenizer.config.preproc, Normalizer::default());
+    assert_eq!(tokenizer.config.min_frequency, None);
+    assert_eq!(tokenizer.encode("hi hi"), vec![256, 32, 256]);
+
+    Ok(())
+}
+
+#[rstest]
+fn test_newer_format_version_is_rejected(
+    tmpdir: &TempDir,
+    tokenizer: &BPETokenizer,
+) -> Result<(), TokenizerError> {
+    let newer = toktkn::schema::FORMAT_VERSION + 1;
+
+    let file_path = tmpdir.path().join("newer_tokenizer.json");
+    tokenizer.save_pretrained(&file_path)?;
+    let mut json: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&file_path)?)?;
+    json["format_version"] = newer.into();
+    std::fs::write(&file_path, json.to_string())?;
+
+    let result = BPETokenizer::from_file(&file_path);
+    assert!(matches!(
+        result,
+        Err(TokenizerError::UnsupportedVersion { found, supported })
+            if found == newer && supported == toktkn::schema::FORMAT_VERSION
+    ));
+    // serde can only carry the message
+    assert!(matches!(
+        BPETokenizer::from_pretrained(&file_path),
+        Err(TokenizerError::Serialization(_))
+    ));
+
+    let file_path = tmpdir.path().join("newer_config.json");
+    std::fs::write(
+        &file_path,
+        format!(r#"{{"format_version":{},"vocab_size":42}}"#, newer),
+    )?;
+    let result = TokenizerConfig::from_file(&file_path);
+    assert!(matches!(result, Err(TokenizerError::UnsupportedVersion { .. })));
+
+    Ok(())
+}
+
+#[derive(serde::Serialize, serde::Deserialize, PartialEq, Debug)]
+struct Settings {
+    vocab_size: usize,
+    name: String,
+}
+
+#[rstest]
+fn test_pretrained_works_for_any_serde_type(tmpdir: &TempDir) -> Result<(), TokenizerError> {
+    let file_path = tmpdir.path().join("settings.json");
+    let settings = Settings {
+        vocab_size: 42,
+        name: "tiny".to_string(),
+    };
+    settings.save_pretrained(&file_path)?;
+    assert_eq!(Settings::from_pretrained(&file_path)?, settings);
+
+    Ok(())
+}
+
+#[rstest]
+// merge id outside the id map
+#[case(r#"[[["104", "105"], "5000"]]"#, "null", true)]
+// merge made of itself, decoding would never finish
+#[case(r#"[[["300", "1"], "300"]]"#, "null", false)]
+// merged id among the raw bytes
+#[case(r#"[[["104", "105"], "100"]]"#, "null", false)]
+// special token among the raw bytes
+#[case("[]", r#"{"<s>": 5}"#, false)]
+fn test_json_rejects_invalid_ids(
+    tmpdir: &TempDir,
+    #[case] encoder: &str,
+    #[case] specials: &str,
+    #[case] with_ids: bool,
+) -> Result<(), TokenizerError> {
+    let ids = match with_ids {
+        true => format!(r#", "ids": {:?}"#, (0..257).collect::<Vec<u32>>()),
+        false => String::new(),
+    };
+    // version 2 files carry no fingerprint to catch any of this
+    let file_path = tmpdir.path().join("invalid_ids.json");
+    std::fs::write(
+        &file_path,
+        format!(
+            r#"{{
+                "format_version": 2,
+                "encoder": {},
+                "config": {{"vocab_size": 1, "special_tokens_map": {}}}{}
+            }}"#,
+            encoder, specials, ids
+        ),
+    )?;
+
+    assert!(matches!(BPETokenizer::from_file(&file_path), Err(TokenizerError::Corrupt(_))));
+    assert!(BPETokenizer::from_pretrained(&file_path).is_err());
+
+    Ok(())
+}
@@ -0,0 +1,47 @@
+use rstest::*;
+use toktkn::{BPETokenizer, StreamDecoder, Token, Tokenizer, TokenizerError};
+
+use crate::helpers::{get_sentence, tokenizer};
+
+#[rstest]
+fn test_stream_matches_decode(tokenizer: &BPETokenizer) {
+    let text = String::from("déjà vu 🦀🦀 東京 ") + &get_sentence();
+    let encoded = tokenizer.encode(&text);
+
+    let mut stream = StreamDecoder::new(tokenizer);
+    let mut fragments: Vec<String> = encoded
+        .iter()
+        .filter_map(|&id| stream.step(id).unwrap())
+        .collect();
+    fragments.extend(stream.flush());
+
+    assert_eq!(fragments.concat(), text);
+}
+
+#[rstest]
+fn test_stream_buffers_partial_characters(tokenizer: &BPETokenizer) {
+    let crab: Vec<Token> = "🦀".bytes().map(Token::from).collect();
+    let mut stream = StreamDecoder::new(tokenizer);
+
+    assert_eq!(stream.step(crab[0]).unwrap(), None);
+    assert_eq!(stream.step(crab[1]).unwrap(), None);
+    assert_eq!(stream.step(crab[2]).unwrap(), None);
+    assert_eq!(stream.step(crab[3]).unwrap(), Some("🦀".to_string()));
+
+    // dangling half character at the end of the stream
+    assert_eq!(stream.step(crab[0]).unwrap(), None);
+    assert_eq!(stream.flush(), Some("\u{FFFD}".to_string()));
+    assert_eq!(stream.flush(), None);
+}
+
+#[rstest]
+fn test_stream_invalid_input(tokenizer: &BPETokenizer) {
+    let mut stream = StreamDecoder::new(tokenizer);
+
+    assert!(matches!(
+        stream.step(1_000_000),
+        Err(TokenizerError::UnknownToken(1_000_000))
+    ));
+    // a lone continuation byte can never become valid
+    assert_eq!(stream.step(0x80).unwrap(), Some("\u{FFFD}".to_string()));
+}
@@ -0,0 +1,145 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use rstest::*;
+use std::collections::HashMap;
+use tempdir::TempDir;
+use toktkn::{
+    config::TokenizerConfig, pretokenizer::PreTokenizer, BPETokenizer, Tokenizer, TokenizerError,
+};
+
+use crate::helpers::{get_corpus, get_sentence, tmpdir, tokenizer};
+
+type Ranks = HashMap<Vec<u8>, u32>;
+
+// a trained tokenizer written out as tiktoken ranks, bytes ranked in reverse
+fn ranks_of(tok: &BPETokenizer) -> Ranks {
+    let mut ranks: Ranks = (0..=255u8).map(|b| (vec![b], 255 - b as u32)).collect();
+    for &id in tok.encoder.values() {
+        ranks.insert(tok.id_to_bytes(id).unwrap(), id);
+    }
+    ranks
+}
+
+fn write_ranks(ranks: &Ranks, path: &std::path::Path) {
+    let mut lines: Vec<(u32, String)> = ranks
+        .iter()
+        .map(|(bytes, &rank)| (rank, format!("{} {}\n", STANDARD.encode(bytes), rank)))
+        .collect();
+    lines.sort();
+    std::fs::write(path, lines.into_iter().map(|(_, l)| l).collect::<String>()).unwrap();
+}
+
+// tiktoken's encoder: whole pieces first, then merges by rank of the merged bytes
+fn reference_encode(ranks: &Ranks, pretokenizer: PreTokenizer, text: &str) -> Vec<u32> {
+    let mut ids = Vec::new();
+    for piece in pretokenizer.into_strategy().pre_tokenize(text).unwrap() {
+        let piece = piece.as_bytes();
+        if let Some(&rank) = ranks.get(piece) {
+            ids.push(rank);
+            continue;
+        }
+
+        let mut parts: Vec<&[u8]> = piece.chunks(1).collect();
+        loop {
+            let best = (0..parts.len().saturating_sub(1))
+                .filter_map(|i| {
+                    let merged = [parts[i], parts[i + 1]].concat();
+                    ranks.get(&merged).map(|&rank| (rank, i))
+                })
+                .min();
+            let Some((_, i)) = best else { break };
+            let len = parts[i].len() + parts[i + 1].len();
+            let start = parts[i].as_ptr() as usize - piece.as_ptr() as usize;
+            parts[i] = &piece[start..start + len];
+            parts.remove(i + 1);
+        }
+        ids.extend(parts.iter().map(|part| ranks[*part]));
+    }
+    ids
+}
+
+#[rstest]
+#[case(PreTokenizer::Gpt2)]
+#[case(PreTokenizer::Cl100k)]
+#[case(PreTokenizer::O200k)]
+fn test_tiktoken_ids_match_reference(tmpdir: &TempDir, #[case] pretokenizer: PreTokenizer) {
+    let mut config = TokenizerConfig::new(120, None);
+    config.pretokenizer = pretokenizer;
+    let mut tok = BPETokenizer::new(config);
+    tok.train(&get_corpus());
+
+    let ranks = ranks_of(&tok);
+    let path = tmpdir.path().join(format!("{:?}.tiktoken", pretokenizer));
+    write_ranks(&ranks, &path);
+
+    let tiktoken =
+        BPETokenizer::from_tiktoken(&path, pretokenizer, &[("<|endoftext|>", 100257)]).unwrap();
+    assert_eq!(tiktoken.len(), tok.len() + 1);
+
+    for _ in 0..10 {
+        let text = get_sentence();
+        let ids = reference_encode(&ranks, pretokenizer, &text);
+        assert_eq!(tiktoken.encode(&text), ids);
+        assert_eq!(tiktoken.encode_sequential(&text).unwrap(), ids);
+        assert_eq!(tiktoken.decode(&ids), text);
+    }
+    assert_eq!(tiktoken.encode("<|endoftext|>"), vec![100257]);
+    assert_eq!(tiktoken.encode("a"), vec![255 - b'a' as u32]);
+}
+
+#[rstest]
+fn test_save_tiktoken_reproduces_file(tokenizer: &BPETokenizer, tmpdir: &TempDir) {
+    let original = tmpdir.path().join("original.tiktoken");
+    write_ranks(&ranks_of(tokenizer), &original);
+
+    let tiktoken = BPETokenizer::from_tiktoken(&original, PreTokenizer::None, &[]).unwrap();
+    let saved = tmpdir.path().join("saved.tiktoken");
+    tiktoken.save_tiktoken(&saved).unwrap();
+
+    assert_eq!(
+        std::fs::read_to_string(saved).unwrap(),
+        std::fs::read_to_string(original).unwrap()
+    );
+
+    // a special token added before training sits between the bytes and the merges
+    let mut tok = BPETokenizer::new(TokenizerConfig::new(60, None));
+    tok.add_special_tokens(vec!["<s>"]);
+    tok.train(&get_corpus());
+
+    let expected = tmpdir.path().join("expected_specials.tiktoken");
+    let mut ranks = ranks_of(&tok);
+    ranks.extend((0..=255u8).map(|b| (vec![b], b as u32)));
+    write_ranks(&ranks, &expected);
+
+    let saved = tmpdir.path().join("saved_specials.tiktoken");
+    tok.save_tiktoken(&saved).unwrap();
+    assert_eq!(
+        std::fs::read_to_string(&saved).unwrap(),
+        std::fs::read_to_string(expected).unwrap()
+    );
+
+    let loaded = BPETokenizer::from_tiktoken(&saved, tok.config.pretokenizer, &[("<s>", 256)]).unwrap();
+    let text = format!("<s>{}", get_sentence());
+    assert_eq!(loaded.encode(&text), tok.encode(&text));
+}
+
+#[rstest]
+// not base64
+#[case("!!! 256\n")]
+// no rank
+#[case("YWI=\n")]
+// every byte but 0xff
+#[case("")]
+// "abc" without "ab" or "bc" ranked below it
+#[case("YWJj 256\n")]
+fn test_tiktoken_rejects_malformed_files(tmpdir: &TempDir, #[case] extra: &str) {
+    let bytes: String = (0..=255u8)
+        .filter(|&b| !extra.is_empty() || b != 0xff)
+        .map(|b| format!("{} {}\n", STANDARD.encode([b]), b))
+        .collect();
+    let path = tmpdir.path().join(format!("malformed_{}.tiktoken", extra.len()));
+    std::fs::write(&path, bytes + extra).unwrap();
+
+    let tok = BPETokenizer::from_tiktoken(&path, PreTokenizer::None, &[]);
+    assert!(matches!(tok, Err(TokenizerError::Unsupported(_))));
+}
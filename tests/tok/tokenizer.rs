@@ -1,11 +1,14 @@
 use rstest::*;
 use rustc_hash::FxHashMap;
+use std::ops::ControlFlow;
+use tempdir::TempDir;
 use toktkn::{
-    config::TokenizerConfig, BPETokenizer, Tokenizer
+    config::TokenizerConfig, pretokenizer::PreTokenizer, BPETokenizer, EncodeOptions, Pretrained,
+    SpecialTokenSet, StopReason, Token, Tokenizer, TokenizerError, TrainingObserver, TrainingReport,
 };
 
 // use crate::helpers::{get_corpus, get_sentence};
-use crate::helpers::{get_corpus, get_sentence, tokenizer};
+use crate::helpers::{get_corpus, get_sentence, tmpdir, tokenizer};
 
 #[test]
 fn test_train_works() {
@@ -40,10 +43,10 @@ fn test_special_tokens_map(){
     assert_eq!(
         tok.config.special_tokens_map,
         Some(FxHashMap::from_iter(vec![
-            ("<s>".to_string(), 128),
-            ("hello".to_string(), 129),
-            ("world".to_string(), 130),
-            ("</s>".to_string(), 131)
+            ("<s>".to_string(), 256),
+            ("hello".to_string(), 257),
+            ("world".to_string(), 258),
+            ("</s>".to_string(), 259)
         ]))
     );
 
@@ -72,3 +75,566 @@ fn test_special_tokens_doesnt_break_encoding() {
 
     dbg!(tok.encode(&sample));
 }
+
+#[test]
+fn test_non_ascii_roundtrip() {
+    let config = TokenizerConfig::new(50, None);
+    let mut tok = BPETokenizer::new(config);
+
+    let corpus = "crème brûlée, 東京 🦀🦀 ".repeat(10) + &get_corpus();
+    tok.train(&corpus);
+
+    let text = String::from("déjà vu in 東京 🦀! ") + &get_sentence();
+    let encoded = tok.encode(&text);
+
+    assert!(tok.encoder.values().all(|&t| t >= 256));
+    assert_eq!(tok.decode(&encoded), text);
+}
+
+#[rstest]
+fn test_parallel_encode_matches_sequential(
+    #[values(
+        PreTokenizer::None,
+        PreTokenizer::Whitespace,
+        PreTokenizer::Gpt2,
+        PreTokenizer::Cl100k
+    )]
+    pretokenizer: PreTokenizer,
+) {
+    let mut config = TokenizerConfig::new(50, None);
+    config.pretokenizer = pretokenizer;
+    let mut tok = BPETokenizer::new(config);
+    tok.add_special_tokens(vec!["<s>", "</s>"]);
+    tok.train(&get_corpus());
+
+    // long enough to span many parallel chunks, with multibyte chars and special
+    // tokens landing on arbitrary byte offsets
+    let text: String = (0..200)
+        .map(|i| format!("{}<s>é🦀{}</s>", " ".repeat(i % 7), get_sentence()))
+        .collect();
+    let encoded = tok.encode(&text);
+
+    assert_eq!(encoded, tok.encode_sequential(&text).unwrap());
+    assert_eq!(tok.decode(&encoded), text);
+}
+
+#[rstest]
+fn test_multibyte_special_tokens(tmpdir: &TempDir) {
+    let specials = ["<|→|>", "【CLS】", "🦀", "<|ünï|>"];
+    let mut tok = BPETokenizer::new(TokenizerConfig::new(60, None));
+    tok.add_special_tokens(specials.to_vec());
+    tok.train(&get_corpus());
+    let id = |s: &str| tok.config.special_tokens_map.as_ref().unwrap()[s];
+
+    let text = format!("【CLS】{}<|→|>🦀 crab<|ünï|>", get_sentence());
+    let encoded = tok.encode(&text);
+    for special in specials {
+        assert_eq!(encoded.iter().filter(|&&t| t == id(special)).count(), 1, "{}", special);
+        assert_eq!(tok.id_to_bytes(id(special)).unwrap(), special.as_bytes());
+    }
+    assert_eq!(tok.decode(&encoded), text);
+    assert_eq!(tok.decode_bytes(&encoded).unwrap(), text.as_bytes());
+
+    let mut stream = toktkn::StreamDecoder::new(&tok);
+    let streamed: String = encoded.iter().filter_map(|&t| stream.step(t).unwrap()).collect();
+    assert_eq!(streamed + &stream.flush().unwrap_or_default(), text);
+
+    let path = tmpdir.path().join("multibyte_specials.tkn");
+    tok.save_file(&path).unwrap();
+    let mapped = toktkn::MappedBPETokenizer::open(&path).unwrap();
+    assert_eq!(mapped.encode(&text), encoded);
+    assert_eq!(mapped.decode(&encoded), text);
+}
+
+#[rstest]
+fn test_overlapping_special_tokens() {
+    let mut tok = BPETokenizer::new(TokenizerConfig::new(30, None));
+    tok.add_special_tokens(vec!["<s>", "<sep>", "<s><s>", "<"]);
+    tok.train(&get_corpus());
+    let id = |s: &str| tok.config.special_tokens_map.as_ref().unwrap()[s];
+
+    // longest match wins wherever several start at the same byte
+    let text = "<s><s><s><sep><se";
+    let encoded = tok.encode(text);
+    assert_eq!(&encoded[..4], &[id("<s><s>"), id("<s>"), id("<sep>"), id("<")]);
+    assert_eq!(tok.decode(&encoded), text);
+}
+
+#[rstest]
+fn test_allowed_and_disallowed_special(tmpdir: &TempDir) {
+    let mut tok = BPETokenizer::new(TokenizerConfig::new(40, None));
+    tok.add_special_tokens(vec!["<s>", "<sep>"]);
+    tok.train(&get_corpus());
+    let id = |s: &str| tok.config.special_tokens_map.as_ref().unwrap()[s];
+    let text = "a<s>b<sep>";
+
+    // not allowed, so encoded like any other text
+    let plain = EncodeOptions {
+        allowed_special: SpecialTokenSet::none(),
+        ..Default::default()
+    };
+    let encoded = tok.try_encode_with(text, &plain).unwrap();
+    assert!(!encoded.contains(&id("<s>")) && !encoded.contains(&id("<sep>")));
+    assert_eq!(tok.decode(&encoded), text);
+
+    let only_s = EncodeOptions {
+        allowed_special: SpecialTokenSet::only(["<s>"]),
+        ..Default::default()
+    };
+    let encoded = tok.try_encode_with(text, &only_s).unwrap();
+    assert!(encoded.contains(&id("<s>")) && !encoded.contains(&id("<sep>")));
+
+    let strict = EncodeOptions {
+        allowed_special: SpecialTokenSet::only(["<s>"]),
+        disallowed_special: SpecialTokenSet::All,
+    };
+    assert!(matches!(
+        tok.try_encode_with(text, &strict),
+        Err(TokenizerError::DisallowedSpecial(token)) if token == "<sep>"
+    ));
+    assert!(tok.try_encode_with("a<s>b", &strict).is_ok());
+
+    let path = tmpdir.path().join("allowed_special.tkn");
+    tok.save_file(&path).unwrap();
+    let mapped = toktkn::MappedBPETokenizer::open(&path).unwrap();
+    for options in [&plain, &only_s] {
+        assert_eq!(
+            mapped.try_encode_with(text, options).unwrap(),
+            tok.try_encode_with(text, options).unwrap()
+        );
+    }
+    assert!(matches!(
+        mapped.try_encode_with(text, &strict),
+        Err(TokenizerError::DisallowedSpecial(_))
+    ));
+}
+
+#[rstest]
+fn test_empty_input(tokenizer: &BPETokenizer) {
+    assert!(tokenizer.try_encode("").unwrap().is_empty());
+    assert_eq!(tokenizer.try_decode(&[]).unwrap(), "");
+
+    let mut tok = BPETokenizer::new(TokenizerConfig::new(10, None));
+    let report = tok.try_train("").unwrap();
+    assert!(report.merges.is_empty());
+    assert_eq!(report.stop_reason, StopReason::NoPairsLeft);
+}
+
+#[rstest]
+fn test_try_decode_errors(tokenizer: &BPETokenizer) {
+    // lone utf-8 continuation byte
+    assert!(matches!(
+        tokenizer.try_decode(&[0x80]),
+        Err(TokenizerError::Utf8(_))
+    ));
+
+    assert!(matches!(
+        tokenizer.try_decode(&[104, 1_000_000]),
+        Err(TokenizerError::UnknownToken(1_000_000))
+    ));
+}
+
+#[test]
+fn test_decode_after_direct_encoder_change() {
+    let mut tok = BPETokenizer::new(TokenizerConfig::new(1, None));
+    tok.train("abab");
+    assert_eq!(tok.decode(&[256]), "ab");
+
+    // decoder was built on the call above, it has to notice the new merge
+    tok.encoder.insert((256, 256), 257);
+    assert_eq!(tok.decode(&[257]), "abab");
+}
+
+#[rstest]
+fn test_decode_bytes_and_lossy(tokenizer: &BPETokenizer) {
+    let text = "🦀 crab";
+    let encoded = tokenizer.encode(text);
+
+    assert_eq!(tokenizer.decode_bytes(&encoded).unwrap(), text.as_bytes());
+
+    // cut the 4-byte crab in half
+    let prefix: Vec<Token> = "🦀".bytes().take(2).map(Token::from).collect();
+    assert!(tokenizer.try_decode(&prefix).is_err());
+    assert_eq!(tokenizer.decode_bytes(&prefix).unwrap(), vec![0xF0, 0x9F]);
+    assert_eq!(tokenizer.decode_lossy(&prefix), "\u{FFFD}");
+
+    assert_eq!(tokenizer.decode_lossy(&[104, 1_000_000, 105]), "h\u{FFFD}i");
+}
+
+#[rstest]
+fn test_id_to_bytes(tokenizer: &BPETokenizer) {
+    assert_eq!(tokenizer.id_to_bytes(104), Some(vec![104]));
+    assert_eq!(tokenizer.id_to_bytes(1_000_000), None);
+
+    for (&(l, r), &t) in tokenizer.encoder.iter() {
+        let expected = [
+            tokenizer.id_to_bytes(l).unwrap(),
+            tokenizer.id_to_bytes(r).unwrap(),
+        ]
+        .concat();
+        assert_eq!(tokenizer.id_to_bytes(t), Some(expected));
+    }
+}
+
+/// textbook bpe: merge the leftmost occurrence of the lowest ranked pair until none are left
+fn naive_encode(tok: &BPETokenizer, text: &str) -> Vec<Token> {
+    let mut tokens: Vec<Token> = text.bytes().map(Token::from).collect();
+
+    loop {
+        let best = tokens
+            .windows(2)
+            .enumerate()
+            .filter_map(|(i, pair)| tok.encoder.get(&(pair[0], pair[1])).map(|&rank| (rank, i)))
+            .min();
+
+        match best {
+            Some((rank, i)) => {
+                tokens[i] = rank;
+                tokens.remove(i + 1);
+            }
+            None => return tokens,
+        }
+    }
+}
+
+#[test]
+fn test_encode_matches_naive_bpe() {
+    use fake::rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+
+    // a small alphabet makes for long and overlapping merge chains
+    let alphabet: Vec<char> = "ab c🦀".chars().collect();
+    let mut rng = StdRng::seed_from_u64(42);
+    let random_text = |rng: &mut StdRng, len: usize| -> String {
+        (0..len).map(|_| *alphabet.choose(rng).unwrap()).collect()
+    };
+
+    let mut config = TokenizerConfig::new(80, None);
+    config.pretokenizer = PreTokenizer::None;
+    let mut tok = BPETokenizer::new(config);
+    tok.train(&random_text(&mut rng, 5000));
+
+    for _ in 0..200 {
+        let len = rng.gen_range(0..300);
+        let text = random_text(&mut rng, len);
+        assert_eq!(tok.encode(&text), naive_encode(&tok, &text), "{:?}", text);
+    }
+}
+
+#[test]
+fn test_train_matches_naive_recount() {
+    let corpus = get_corpus();
+    let mut tok = BPETokenizer::new(TokenizerConfig::new(100, None));
+    tok.train(&corpus);
+
+    // recount every pair on each iteration, ties going to the smallest byte sequences
+    let pretokenizer = tok.config.pretokenizer.into_strategy();
+    let mut words: Vec<Vec<Token>> = pretokenizer
+        .pre_tokenize(&corpus)
+        .unwrap()
+        .iter()
+        .map(|w| w.bytes().map(Token::from).collect())
+        .collect();
+
+    let mut expected = FxHashMap::default();
+    for token_id in 256..356 {
+        let mut counts: FxHashMap<(Token, Token), usize> = FxHashMap::default();
+        for word in words.iter() {
+            for pair in word.windows(2) {
+                *counts.entry((pair[0], pair[1])).or_default() += 1;
+            }
+        }
+        let bytes = |pair: &(Token, Token)| (tok.id_to_bytes(pair.0), tok.id_to_bytes(pair.1));
+        let Some((&pair, _)) = counts
+            .iter()
+            .max_by(|a, b| a.1.cmp(b.1).then_with(|| bytes(b.0).cmp(&bytes(a.0))))
+        else {
+            break;
+        };
+
+        expected.insert(pair, token_id);
+        for word in words.iter_mut() {
+            let mut i = 0;
+            while i + 1 < word.len() {
+                if (word[i], word[i + 1]) == pair {
+                    word[i] = token_id;
+                    word.remove(i + 1);
+                }
+                i += 1;
+            }
+        }
+    }
+
+    assert_eq!(tok.encoder, expected);
+}
+
+#[rstest]
+fn test_train_from_iterator_and_files(tmpdir: &TempDir) {
+    let corpus = get_corpus();
+    let mut expected = BPETokenizer::new(TokenizerConfig::new(60, None));
+    expected.train(&corpus);
+
+    // shard the corpus on pre-token boundaries
+    let pieces = expected
+        .config
+        .pretokenizer
+        .into_strategy()
+        .pre_tokenize(&corpus)
+        .unwrap();
+    let shards: Vec<String> = pieces.chunks(25).map(|c| c.concat()).collect();
+
+    let mut tok = BPETokenizer::new(TokenizerConfig::new(60, None));
+    tok.train_from_iterator(shards.iter()).unwrap();
+    assert_eq!(tok.encoder, expected.encoder);
+
+    let paths: Vec<_> = shards
+        .chunks(shards.len() / 3 + 1)
+        .enumerate()
+        .map(|(i, c)| {
+            let path = tmpdir.path().join(format!("shard_{}.txt", i));
+            std::fs::write(&path, c.concat()).unwrap();
+            path
+        })
+        .collect();
+
+    let mut tok = BPETokenizer::new(TokenizerConfig::new(60, None));
+    tok.train_from_files(&paths).unwrap();
+    assert_eq!(tok.encoder, expected.encoder);
+
+    let missing = tok.train_from_files(&[tmpdir.path().join("missing.txt")]);
+    assert!(matches!(missing, Err(TokenizerError::Io(_))));
+}
+
+#[rstest]
+#[case(Some(1))]
+#[case(Some(4))]
+#[case(None)]
+fn test_train_is_independent_of_thread_count(#[case] num_threads: Option<usize>) {
+    let corpus = get_corpus();
+
+    let mut config = TokenizerConfig::new(120, None);
+    config.num_threads = Some(2);
+    let mut expected = BPETokenizer::new(config);
+    expected.train(&corpus);
+
+    let mut config = TokenizerConfig::new(120, None);
+    config.num_threads = num_threads;
+    let mut tok = BPETokenizer::new(config);
+    tok.train(&corpus);
+
+    assert_eq!(tok.encoder, expected.encoder);
+}
+
+#[rstest]
+fn test_train_is_reproducible(tmpdir: &TempDir) {
+    let corpus = get_corpus();
+
+    let files: Vec<Vec<u8>> = (0..2)
+        .map(|i| {
+            let mut tok = BPETokenizer::new(TokenizerConfig::new(200, None));
+            tok.add_special_tokens(vec!["<s>", "</s>", "<pad>"]);
+            tok.train(&corpus);
+
+            let path = tmpdir.path().join(format!("reproducible_{}.json", i));
+            tok.save_pretrained(&path).unwrap();
+            std::fs::read(path).unwrap()
+        })
+        .collect();
+
+    assert_eq!(files[0], files[1]);
+}
+
+#[test]
+fn test_config_rejects_zero_vocab_size() {
+    assert!(matches!(
+        TokenizerConfig::try_new(0, None),
+        Err(TokenizerError::InvalidArgument(_))
+    ));
+    assert!(TokenizerConfig::try_new(1, None).is_ok());
+}
+
+#[test]
+fn test_train_reports_stop_reason() {
+    let corpus = get_corpus();
+
+    let mut tok = BPETokenizer::new(TokenizerConfig::new(100, None));
+    let report = tok.train(&corpus);
+    assert_eq!(report.stop_reason, StopReason::VocabSizeReached);
+    assert_eq!(report.merges.len(), 100);
+    assert!(report.merges.iter().all(|(p, id)| tok.encoder[p] == *id));
+
+    // already at vocab_size
+    let report = tok.train(&corpus);
+    assert_eq!(report.stop_reason, StopReason::VocabSizeReached);
+    assert!(report.merges.is_empty());
+
+    let mut tok = BPETokenizer::new(TokenizerConfig::new(100_000, None));
+    let report = tok.train("abab abab");
+    assert_eq!(report.stop_reason, StopReason::NoPairsLeft);
+    assert_eq!(tok.encode("abab abab").len(), 2);
+}
+
+#[test]
+fn test_train_min_frequency() {
+    let corpus = get_corpus();
+
+    let mut config = TokenizerConfig::new(100_000, None);
+    config.min_frequency = Some(50);
+    let mut tok = BPETokenizer::new(config);
+    let mut recorder = Recorder::default();
+    let report = tok.try_train_with_observer(&corpus, &mut recorder).unwrap();
+    assert_eq!(report.stop_reason, StopReason::BelowMinFrequency);
+    assert!(!report.merges.is_empty());
+
+    // every merge learned was frequent enough
+    assert_eq!(recorder.merges.len(), report.merges.len());
+    assert!(recorder.merges.iter().all(|&(_, _, count)| count >= 50));
+
+    // stops exactly where an unrestricted run drops below the threshold
+    let mut full = BPETokenizer::new(TokenizerConfig::new(100_000, None));
+    let mut full_recorder = Recorder::default();
+    full.try_train_with_observer(&corpus, &mut full_recorder).unwrap();
+    let expected: Vec<_> = full_merges(&full)
+        .into_iter()
+        .take(report.merges.len())
+        .collect();
+    assert_eq!(report.merges, expected);
+
+    // the next candidate pair is below the threshold
+    let (_, _, next) = full_recorder.merges[report.merges.len()];
+    assert!(next < 50);
+}
+
+#[test]
+fn test_train_max_token_length() {
+    let corpus = get_corpus();
+
+    let mut config = TokenizerConfig::new(300, None);
+    config.max_token_length = Some(3);
+    let mut tok = BPETokenizer::new(config);
+    let report = tok.train(&corpus);
+    assert!(!report.merges.is_empty());
+
+    for (_, id) in report.merges {
+        assert!(tok.id_to_bytes(id).unwrap().len() <= 3);
+    }
+}
+
+// merges of a tokenizer in the order they were learned
+fn full_merges(tok: &BPETokenizer) -> Vec<((Token, Token), Token)> {
+    let mut merges: Vec<_> = tok.encoder.iter().map(|(&p, &id)| (p, id)).collect();
+    merges.sort_by_key(|&(_, id)| id);
+    merges
+}
+
+#[derive(Default)]
+struct Recorder {
+    merges: Vec<((Token, Token), Token, u64)>,
+    progress: Vec<(usize, usize)>,
+    finished: Option<StopReason>,
+}
+
+impl TrainingObserver for Recorder {
+    fn on_merge(&mut self, pair: (Token, Token), token: Token, count: u64) -> ControlFlow<()> {
+        self.merges.push((pair, token, count));
+        ControlFlow::Continue(())
+    }
+
+    fn on_progress(&mut self, done: usize, total: usize) -> ControlFlow<()> {
+        self.progress.push((done, total));
+        ControlFlow::Continue(())
+    }
+
+    fn on_finish(&mut self, report: &TrainingReport) -> ControlFlow<()> {
+        self.finished = Some(report.stop_reason);
+        ControlFlow::Continue(())
+    }
+}
+
+// stops training after `left` more merges
+struct Stop {
+    left: usize,
+}
+
+impl TrainingObserver for Stop {
+    fn on_merge(&mut self, _pair: (Token, Token), _token: Token, _count: u64) -> ControlFlow<()> {
+        self.left -= 1;
+        match self.left {
+            0 => ControlFlow::Break(()),
+            _ => ControlFlow::Continue(()),
+        }
+    }
+}
+
+#[test]
+fn test_observer_stops_training() {
+    let corpus = get_corpus();
+    let mut tok = BPETokenizer::new(TokenizerConfig::new(20, None));
+    tok.train(&corpus);
+    let before = tok.encoder.clone();
+
+    let result = tok.extend_with_observer(&corpus, 30, &mut Stop { left: 10 });
+    assert!(matches!(result, Err(TokenizerError::Interrupted)));
+    assert_eq!(tok.encoder, before);
+    assert_eq!(tok.config.vocab_size, 20);
+    assert_eq!(tok.decode(&tok.encode(&corpus)), corpus);
+}
+
+#[test]
+fn test_train_observer() {
+    let mut tok = BPETokenizer::new(TokenizerConfig::new(50, None));
+    let mut recorder = Recorder::default();
+    let report = tok
+        .try_train_with_observer(&get_corpus(), &mut recorder)
+        .unwrap();
+
+    let merges: Vec<_> = recorder.merges.iter().map(|&(p, id, _)| (p, id)).collect();
+    assert_eq!(merges, report.merges);
+    assert!(recorder.merges.windows(2).all(|w| w[0].2 >= w[1].2));
+
+    assert_eq!(recorder.progress.len(), 51);
+    assert_eq!(recorder.progress[0], (0, 50));
+    assert_eq!(recorder.progress[50], (50, 50));
+    assert_eq!(recorder.finished, Some(StopReason::VocabSizeReached));
+}
+
+#[test]
+fn test_extend_keeps_existing_ids() {
+    let mut tok = BPETokenizer::new(TokenizerConfig::new(60, None));
+    tok.train(&get_corpus());
+    tok.add_special_tokens(vec!["<eos>"]);
+
+    let base = tok.encoder.clone();
+    let specials = tok.config.special_tokens_map.clone();
+    let before = tok.len();
+
+    let domain = "fn main() { println!(\"{}\", x); } ".repeat(50);
+    let report = tok.extend(&domain, 20).unwrap();
+
+    assert_eq!(report.stop_reason, StopReason::VocabSizeReached);
+    assert_eq!(report.merges.len(), 20);
+    assert_eq!(tok.len(), before + 20);
+    assert_eq!(tok.config.vocab_size, before + 20);
+    assert_eq!(tok.config.special_tokens_map, specials);
+
+    // old merges untouched, new ones appended after every existing id
+    for (pair, id) in base.iter() {
+        assert_eq!(tok.encoder[pair], *id);
+    }
+    for (i, (pair, id)) in report.merges.iter().enumerate() {
+        assert!(!base.contains_key(pair));
+        assert_eq!(*id as usize, 256 + before + i);
+    }
+
+    let text = format!("{}<eos>{}", get_sentence(), domain);
+    assert_eq!(tok.decode(&tok.encode(&text)), text);
+}
+
+#[test]
+fn test_extend_stopping_early_keeps_vocab_size() {
+    let mut tok = BPETokenizer::new(TokenizerConfig::new(10, None));
+    tok.train(&get_corpus());
+
+    let report = tok.extend("abab abab", 1000).unwrap();
+    assert_eq!(report.stop_reason, StopReason::NoPairsLeft);
+    assert_eq!(tok.config.vocab_size, tok.len());
+}